@@ -0,0 +1,34 @@
+#[macro_use]
+extern crate criterion;
+extern crate rust_tmp;
+
+use criterion::{black_box, Criterion};
+use rust_tmp::tokenizer::Tokenizer;
+
+/// Builds a program of `lines` lines, with `extra` appended to each so that
+/// the input can be made to contain non-ASCII chars.
+fn program(lines: usize, extra: &str) -> String {
+    let mut source = String::new();
+    for i in 0..lines {
+        source.push_str(&format!(
+            "let value_{} := {} * (counter + 1_000) - 0x1F; // note{}\n",
+            i, i, extra
+        ));
+    }
+    source
+}
+
+fn tokenize(c: &mut Criterion) {
+    let ascii = program(10_000, "");
+    let unicode = program(10_000, " é");
+
+    c.bench_function("tokenize ascii", |b| {
+        b.iter(|| Tokenizer::new(black_box(&ascii)).count())
+    });
+    c.bench_function("tokenize non-ascii", |b| {
+        b.iter(|| Tokenizer::new(black_box(&unicode)).count())
+    });
+}
+
+criterion_group!(benches, tokenize);
+criterion_main!(benches);
@@ -0,0 +1,239 @@
+//! Rendering of error messages against the source they refer to.
+//!
+//! ```
+//! use rust_tmp::diagnostics;
+//! use rust_tmp::tokenizer::Span;
+//!
+//! let span = Span { start: 4, end: 5, line: 1, col: 4 };
+//! assert_eq!(
+//!     diagnostics::render("let @ := 1;", span, "unexpected char"),
+//!     "1 | let @ := 1;\n  |     ^ unexpected char\n"
+//! );
+//! ```
+
+use parser::ParseError;
+use tokenizer::{LexError, Span, DEFAULT_TAB_WIDTH};
+
+/// Renders `message` below the line of `source` that `span` starts on, with
+/// carets under the spanned text. Tabs in the line are expanded so that the
+/// carets line up, and a span reaching past the end of its line is cut off
+/// there. An empty span gets a single caret.
+pub fn render(source: &str, span: Span, message: &str) -> String {
+    render_with(source, span, message, DEFAULT_TAB_WIDTH)
+}
+
+/// Renders like `render`, expanding tabs to stops `tab_width` columns apart,
+/// for source read by a tokenizer set up with `Tokenizer::with_tab_width`.
+pub fn render_with(source: &str, span: Span, message: &str, tab_width: u32) -> String {
+    let tab_width = tab_width.max(1) as usize;
+    let start = span.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+    let line = source[line_start..line_end].trim_end_matches('\r');
+    let end = span.end.max(start).min(line_start + line.len());
+
+    let mut text = String::new();
+    let mut column = 0;
+    let mut caret_start = None;
+    let mut caret_end = 0;
+    for (i, c) in line.char_indices() {
+        let offset = line_start + i;
+        if offset == start {
+            caret_start = Some(column);
+        }
+        if c == '\t' {
+            let width = tab_width - column % tab_width;
+            text.push_str(&" ".repeat(width));
+            column += width;
+        } else {
+            text.push(c);
+            column += 1;
+        }
+        if offset < end {
+            caret_end = column;
+        }
+    }
+    let caret_start = caret_start.unwrap_or(column);
+    let carets = caret_end.saturating_sub(caret_start).max(1);
+
+    let number = span.line.to_string();
+    let gutter = " ".repeat(number.len());
+    format!(
+        "{} | {}\n{} | {}{} {}\n",
+        number,
+        text,
+        gutter,
+        " ".repeat(caret_start),
+        "^".repeat(carets),
+        message
+    )
+}
+
+/// How serious a diagnostic is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Severity {
+    /// A problem that stops the program from running.
+    Error,
+    /// A likely mistake that does not stop the program from running.
+    Warning,
+}
+
+/// A message about a point in source, in a form that tools such as editors
+/// can read without parsing error text.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diagnostic {
+    /// The line the problem starts on, counting from 1.
+    pub line: u32,
+    /// The column the problem starts at, counting from 0.
+    pub col: u32,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    /// Describes `err`.
+    pub fn from_lex_error(err: &LexError) -> Self {
+        let (line, col) = err.position();
+        Diagnostic {
+            line,
+            col,
+            message: err.message(),
+            severity: Severity::Error,
+        }
+    }
+
+    /// Describes `err`.
+    pub fn from_parse_error(err: &ParseError) -> Self {
+        let span = err.span();
+        Diagnostic {
+            line: span.line,
+            col: span.col,
+            message: err.message(),
+            severity: Severity::Error,
+        }
+    }
+}
+
+/// Serializes `diagnostics` as a JSON array of objects with the fields
+/// `line`, `col`, `message` and `severity`.
+///
+/// ```
+/// use rust_tmp::diagnostics::{self, Diagnostic, Severity};
+///
+/// let diagnostic = Diagnostic {
+///     line: 1,
+///     col: 4,
+///     message: "unexpected char '@'".to_string(),
+///     severity: Severity::Error,
+/// };
+/// assert_eq!(
+///     diagnostics::to_json(&[diagnostic]),
+///     r#"[{"line":1,"col":4,"message":"unexpected char '@'","severity":"error"}]"#
+/// );
+/// ```
+#[cfg(feature = "serde")]
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    serde_json::to_string(diagnostics).expect("diagnostics always serialize")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, render_with};
+    use tokenizer::Span;
+
+    fn span(start: usize, end: usize, line: u32, col: u32) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            col,
+        }
+    }
+
+    #[test]
+    fn mid_line() {
+        let source = "let x := 1;\nprint x +* 2;\n";
+
+        assert_eq!(
+            render(source, span(21, 23, 2, 9), "unexpected '*'"),
+            "2 | print x +* 2;\n  |          ^^ unexpected '*'\n"
+        );
+    }
+
+    #[test]
+    fn line_edges() {
+        let source = "print 1\r\nx";
+
+        assert_eq!(
+            render(source, span(0, 5, 1, 0), "here"),
+            "1 | print 1\n  | ^^^^^ here\n"
+        );
+        assert_eq!(
+            render(source, span(7, 7, 1, 7), "expected ';'"),
+            "1 | print 1\n  |        ^ expected ';'\n"
+        );
+        assert_eq!(
+            render(source, span(6, 10, 1, 6), "spans lines"),
+            "1 | print 1\n  |       ^ spans lines\n"
+        );
+    }
+
+    #[test]
+    fn tabs() {
+        let source = "if x {\n\tprint\t@;\n}";
+
+        assert_eq!(
+            render(source, span(14, 15, 2, 7), "unexpected char"),
+            "2 |     print   @;\n  |             ^ unexpected char\n"
+        );
+        assert_eq!(
+            render_with(source, span(14, 15, 2, 8), "unexpected char", 2),
+            "2 |   print @;\n  |         ^ unexpected char\n"
+        );
+    }
+
+    #[test]
+    fn wide_line_numbers() {
+        let source = "\n\n\n\n\n\n\n\n\nx y";
+
+        assert_eq!(
+            render(source, span(11, 12, 10, 2), "oops"),
+            "10 | x y\n   |   ^ oops\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        use super::{to_json, Diagnostic};
+        use parser;
+        use tokenizer::Tokenizer;
+
+        let source = "let x := 1;\n  print x @;";
+        let lex_error = Tokenizer::new(source)
+            .checked()
+            .find_map(Result::err)
+            .unwrap();
+        let parse_error = parser::parse("print (1;").unwrap_err();
+        let diagnostics = vec![
+            Diagnostic::from_lex_error(&lex_error),
+            Diagnostic::from_parse_error(&parse_error),
+        ];
+        assert_eq!(
+            to_json(&diagnostics),
+            r#"[{"line":2,"col":10,"message":"unexpected char '@'","severity":"error"},"#
+                .to_string()
+                + r#"{"line":1,"col":8,"message":"expected ')', found ';'","severity":"error"}]"#
+        );
+
+        // The end of input is placed just after the last token.
+        let eof = parser::parse("print 1 +\n").unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(&eof);
+        assert_eq!((diagnostic.line, diagnostic.col), (1, 9));
+    }
+}
@@ -0,0 +1,1403 @@
+//! Execution of parsed ALAN programs.
+//!
+//! ```
+//! use rust_tmp::interpreter::Interpreter;
+//! use rust_tmp::parser;
+//!
+//! let stmts = parser::parse("let x := 6; print x * 7;").unwrap();
+//! let mut out = Vec::new();
+//! Interpreter::new(&mut out).eval(&stmts).unwrap();
+//! assert_eq!(out, b"42\n");
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+
+use ops::{self, BinaryOp, OpError, Scalar, UnaryOp};
+use parser::{Expr, Format, Stmt, Type};
+use tokenizer::Span;
+
+/// A value computed by a running program.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Boolean(bool),
+    Integer(i32),
+    String(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// The name of this value's type, for use in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) => "integer",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+        }
+    }
+
+    /// Returns the value as an operand for `ops`, unless it is an array.
+    fn into_scalar(self) -> Option<Scalar> {
+        match self {
+            Value::Boolean(b) => Some(Scalar::Boolean(b)),
+            Value::Integer(n) => Some(Scalar::Integer(n)),
+            Value::String(s) => Some(Scalar::String(s)),
+            Value::Array(_) => None,
+        }
+    }
+
+    /// Whether the value counts as true where a condition is expected. This
+    /// coercion is deliberate, so that `while count { ... }` runs until
+    /// `count` is 0: integers are true unless 0, and strings and arrays
+    /// unless empty. Operators such as `not` and `and` still only take
+    /// booleans.
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            Value::Boolean(b) => b,
+            Value::Integer(n) => n != 0,
+            Value::String(ref s) => !s.is_empty(),
+            Value::Array(ref elements) => !elements.is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::String(ref s) => f.write_str(s),
+            Value::Array(ref elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+impl From<Scalar> for Value {
+    fn from(value: Scalar) -> Self {
+        match value {
+            Scalar::Boolean(b) => Value::Boolean(b),
+            Scalar::Integer(n) => Value::Integer(n),
+            Scalar::String(s) => Value::String(s),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// A variable was read or assigned before any `let` introduced it.
+    UndefinedVariable(String),
+    /// A function was called before any `func` defined it.
+    UndefinedFunction(String),
+    /// A function was called with a different number of arguments than it
+    /// has parameters.
+    WrongArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// An operation was applied to a value of the wrong type.
+    TypeError(String),
+    DivisionByZero,
+    /// An array was indexed outside of its bounds.
+    IndexOutOfBounds {
+        index: i32,
+        len: usize,
+    },
+    /// An array was declared with a size that is not positive or is more
+    /// than `MAX_ARRAY_LEN`.
+    InvalidArraySize(i32),
+    /// Integer arithmetic produced a result that does not fit in an `i32`.
+    Overflow,
+    /// An integer was raised to a negative power.
+    NegativeExponent,
+    /// A call was made while more calls than the interpreter's maximum depth
+    /// were already running.
+    RecursionLimit,
+    /// `return` was run outside of any function.
+    ReturnOutsideFunction,
+    /// An `assert` found its condition falsy. `line` and `col` give the
+    /// position of the `assert` keyword.
+    AssertionFailed {
+        message: Option<String>,
+        line: u32,
+        col: u32,
+    },
+    /// Writing the output of `print` failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeError::UndefinedVariable(ref name) => {
+                write!(f, "undefined variable `{}`", name)
+            }
+            RuntimeError::UndefinedFunction(ref name) => {
+                write!(f, "undefined function `{}`", name)
+            }
+            RuntimeError::WrongArgumentCount {
+                ref name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} argument(s) but was given {}",
+                name, expected, found
+            ),
+            RuntimeError::TypeError(ref message) => f.write_str(message),
+            RuntimeError::DivisionByZero => f.write_str("division by zero"),
+            RuntimeError::IndexOutOfBounds { index, len } => write!(
+                f,
+                "index {} is out of bounds for an array of length {}",
+                index, len
+            ),
+            RuntimeError::InvalidArraySize(size) => write!(
+                f,
+                "array size must be from 1 to {}, not {}",
+                MAX_ARRAY_LEN, size
+            ),
+            RuntimeError::Overflow => f.write_str("integer overflow"),
+            RuntimeError::NegativeExponent => f.write_str("negative exponent"),
+            RuntimeError::RecursionLimit => f.write_str("too many nested calls"),
+            RuntimeError::ReturnOutsideFunction => f.write_str("'return' outside of a function"),
+            RuntimeError::AssertionFailed {
+                ref message,
+                line,
+                col,
+            } => {
+                write!(f, "{}:{}: assertion failed", line, col)?;
+                match *message {
+                    Some(ref message) => write!(f, ": {}", message),
+                    None => Ok(()),
+                }
+            }
+            RuntimeError::Io(ref err) => write!(f, "cannot write output: {}", err),
+        }
+    }
+}
+
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RuntimeError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(err: io::Error) -> Self {
+        RuntimeError::Io(err)
+    }
+}
+
+/// How control leaves a statement.
+enum Flow {
+    /// On to the next statement.
+    Next,
+    Break,
+    Continue,
+    /// Out of the function being run, with the value it produces, if any.
+    Return(Option<Value>),
+}
+
+/// A function defined with `func`.
+struct Function {
+    params: Vec<String>,
+    body: Vec<Stmt>,
+}
+
+/// Runs statements, keeping variables and functions between calls to `eval`
+/// and writing the output of `print` to `out`.
+pub struct Interpreter<W: Write> {
+    /// The variables in scope, innermost scope last. Each block gets a scope
+    /// of its own, which is dropped when the block ends.
+    env: Vec<HashMap<String, Value>>,
+    functions: HashMap<String, Rc<Function>>,
+    out: W,
+    /// The number of calls currently running.
+    depth: usize,
+    max_depth: usize,
+}
+
+/// The variables and functions of an `Interpreter` at some point, taken with
+/// `Interpreter::snapshot` to be put back with `Interpreter::restore`.
+#[derive(Clone)]
+pub struct EnvSnapshot {
+    env: Vec<HashMap<String, Value>>,
+    functions: HashMap<String, Rc<Function>>,
+}
+
+/// The number of calls that may be running at once unless changed with
+/// `Interpreter::set_max_depth`. Each call takes several kilobytes of native
+/// stack in a debug build, so this is low enough for the 2 MiB stack of a
+/// spawned thread.
+pub const DEFAULT_MAX_DEPTH: usize = 200;
+
+/// The most elements an array declared with `array[size]` can have, so that
+/// a huge size is an error rather than an allocation that aborts the process.
+pub const MAX_ARRAY_LEN: i32 = 1 << 20;
+
+impl<W: Write> Interpreter<W> {
+    pub fn new(out: W) -> Self {
+        Interpreter {
+            env: vec![HashMap::new()],
+            functions: HashMap::new(),
+            out,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Sets how many calls may be running at once before a further call fails
+    /// with `RuntimeError::RecursionLimit`.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Captures the variables and functions currently defined.
+    pub fn snapshot(&self) -> EnvSnapshot {
+        EnvSnapshot {
+            env: self.env.clone(),
+            functions: self.functions.clone(),
+        }
+    }
+
+    /// Puts back the variables and functions captured in `snapshot`,
+    /// undoing any changes made since. Output already written is not undone.
+    pub fn restore(&mut self, snapshot: EnvSnapshot) {
+        self.env = snapshot.env;
+        self.functions = snapshot.functions;
+    }
+
+    /// Returns the writer that `print` writes to.
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    /// Executes `stmts` in order, stopping at the first error.
+    pub fn eval(&mut self, stmts: &[Stmt]) -> Result<(), RuntimeError> {
+        match self.exec_block(stmts)? {
+            Flow::Return(_) => Err(RuntimeError::ReturnOutsideFunction),
+            _ => Ok(()),
+        }
+    }
+
+    /// Executes `stmts` in order until one of them breaks out of the normal
+    /// flow of control, returning how it did.
+    fn exec_block(&mut self, stmts: &[Stmt]) -> Result<Flow, RuntimeError> {
+        for stmt in stmts {
+            match self.exec(stmt)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Executes `stmts` like `exec_block`, in a new innermost scope.
+    fn exec_scoped(&mut self, stmts: &[Stmt]) -> Result<Flow, RuntimeError> {
+        self.env.push(HashMap::new());
+        let result = self.exec_block(stmts);
+        self.env.pop();
+        result
+    }
+
+    /// Binds `name` to `value` in the innermost scope, shadowing any binding
+    /// of `name` in an outer scope.
+    fn declare(&mut self, name: &str, value: Value) {
+        self.env
+            .last_mut()
+            .expect("there is always a scope")
+            .insert(name.to_string(), value);
+    }
+
+    /// Returns the innermost binding of `name`.
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.env.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.env
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> Result<Flow, RuntimeError> {
+        match *stmt {
+            Stmt::Let {
+                ref name,
+                ty,
+                ref value,
+            } => self.exec_let(name, ty, value)?,
+            Stmt::ArrayDecl {
+                ref name,
+                ref size,
+                element,
+            } => self.exec_array_decl(name, size, element)?,
+            Stmt::Assign {
+                ref name,
+                ref value,
+            } => self.exec_assign(name, value)?,
+            Stmt::Print(ref args) => self.exec_print(args)?,
+            Stmt::While { ref cond, ref body } => {
+                while self.eval_condition(cond)? {
+                    match self.exec_scoped(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Next | Flow::Continue => {}
+                    }
+                }
+            }
+            Stmt::If {
+                ref cond,
+                ref then_branch,
+                ref else_branch,
+            } => {
+                if self.eval_condition(cond)? {
+                    return self.exec_scoped(then_branch);
+                } else if let Some(ref else_branch) = *else_branch {
+                    return self.exec_scoped(else_branch);
+                }
+            }
+            Stmt::FuncDef {
+                ref name,
+                ref params,
+                ref body,
+            } => {
+                let function = Function {
+                    params: params.clone(),
+                    body: body.clone(),
+                };
+                self.functions.insert(name.clone(), Rc::new(function));
+            }
+            Stmt::Expr(ref expr) => {
+                if let Expr::Call {
+                    ref name, ref args, ..
+                } = *expr
+                {
+                    self.call(name, args)?;
+                } else {
+                    self.eval_expr(expr)?;
+                }
+            }
+            Stmt::ForEach {
+                ref name,
+                ref iterable,
+                ref body,
+            } => return self.exec_for_each(name, iterable, body),
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Continue => return Ok(Flow::Continue),
+            Stmt::Return(ref value) => return self.exec_return(value.as_ref()),
+            Stmt::Assert {
+                ref cond,
+                ref message,
+                span,
+            } => self.exec_assert(cond, message.as_ref(), span)?,
+        }
+        Ok(Flow::Next)
+    }
+
+    // The statements below are run outside of `exec` to keep its stack frame
+    // small, since calls recurse through it.
+
+    fn exec_let(&mut self, name: &str, ty: Option<Type>, value: &Expr) -> Result<(), RuntimeError> {
+        let value = self.eval_expr(value)?;
+        if let Some(ty) = ty {
+            check_type(name, ty, &value)?;
+        }
+        self.declare(name, value);
+        Ok(())
+    }
+
+    fn exec_array_decl(
+        &mut self,
+        name: &str,
+        size: &Expr,
+        element: Type,
+    ) -> Result<(), RuntimeError> {
+        let size = self.eval_expr(size)?;
+        let array = zeroed_array(size, element)?;
+        self.declare(name, array);
+        Ok(())
+    }
+
+    fn exec_assign(&mut self, name: &str, value: &Expr) -> Result<(), RuntimeError> {
+        let value = self.eval_expr(value)?;
+        match self.lookup_mut(name) {
+            Some(slot) => *slot = value,
+            None => return Err(RuntimeError::UndefinedVariable(name.to_string())),
+        }
+        Ok(())
+    }
+
+    fn exec_print(&mut self, args: &[Expr]) -> Result<(), RuntimeError> {
+        let values = args
+            .iter()
+            .map(|arg| self.eval_expr(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                write!(self.out, " ")?;
+            }
+            write!(self.out, "{}", value)?;
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    fn exec_for_each(
+        &mut self,
+        name: &str,
+        iterable: &Expr,
+        body: &[Stmt],
+    ) -> Result<Flow, RuntimeError> {
+        let iterable = self.eval_expr(iterable)?;
+        for item in elements(iterable)? {
+            self.env.push(HashMap::new());
+            self.declare(name, item);
+            let flow = self.exec_block(body);
+            self.env.pop();
+            match flow? {
+                Flow::Break => break,
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+                Flow::Next | Flow::Continue => {}
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_return(&mut self, value: Option<&Expr>) -> Result<Flow, RuntimeError> {
+        let value = match value {
+            Some(value) => Some(self.eval_expr(value)?),
+            None => None,
+        };
+        Ok(Flow::Return(value))
+    }
+
+    fn exec_assert(
+        &mut self,
+        cond: &Expr,
+        message: Option<&Expr>,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        if self.eval_condition(cond)? {
+            return Ok(());
+        }
+        let message = match message {
+            Some(message) => Some(self.eval_expr(message)?.to_string()),
+            None => None,
+        };
+        Err(RuntimeError::AssertionFailed {
+            message,
+            line: span.line,
+            col: span.col,
+        })
+    }
+
+    /// Evaluates the condition of an `if` or `while`, which may be a value
+    /// of any type, taken by its truthiness.
+    fn eval_condition(&mut self, cond: &Expr) -> Result<bool, RuntimeError> {
+        self.eval_expr(cond).map(|value| value.is_truthy())
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match *expr {
+            Expr::Boolean(b) => Ok(Value::Boolean(b)),
+            Expr::Integer(n) => Ok(Value::Integer(n)),
+            Expr::String(ref s) => Ok(Value::String(s.clone())),
+            Expr::Name(ref name) => self
+                .lookup(name)
+                .cloned()
+                .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone())),
+            Expr::ArrayLiteral(ref elements) => elements
+                .iter()
+                .map(|element| self.eval_expr(element))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            Expr::Index {
+                ref target,
+                ref index,
+                ..
+            } => {
+                let target = self.eval_expr(target)?;
+                let index = self.eval_expr(index)?;
+                element_at(target, index)
+            }
+            Expr::Call {
+                ref name, ref args, ..
+            } => self.call(name, args)?.ok_or_else(|| {
+                RuntimeError::TypeError(format!("`{}` does not return a value", name))
+            }),
+            Expr::Unary {
+                op, ref operand, ..
+            } => {
+                let operand = self.eval_expr(operand)?;
+                unary(op, operand)
+            }
+            Expr::Binary {
+                op,
+                ref lhs,
+                ref rhs,
+                ..
+            } => {
+                let lhs = self.eval_expr(lhs)?;
+                if let Some(result) = short_circuit(op, &lhs) {
+                    return Ok(result);
+                }
+                let rhs = self.eval_expr(rhs)?;
+                binary(op, lhs, rhs)
+            }
+            Expr::Block {
+                ref stmts,
+                ref value,
+                ..
+            } => self.eval_block(stmts, value),
+            Expr::Formatted { ref value, format } => {
+                let value = self.eval_expr(value)?;
+                format_integer(value, format)
+            }
+            Expr::If {
+                ref cond,
+                ref then_branch,
+                ref else_branch,
+                ..
+            } => {
+                if self.eval_condition(cond)? {
+                    self.eval_expr(then_branch)
+                } else {
+                    self.eval_expr(else_branch)
+                }
+            }
+        }
+    }
+
+    /// Runs `stmts` in a new scope and evaluates `value` in that scope. The
+    /// parser does not allow `stmts` to break out of the block.
+    fn eval_block(&mut self, stmts: &[Stmt], value: &Expr) -> Result<Value, RuntimeError> {
+        self.env.push(HashMap::new());
+        let result = self.exec_block(stmts).and_then(|_| self.eval_expr(value));
+        self.env.pop();
+        result
+    }
+
+    /// Calls the function `name`, returning the value it produces, if any.
+    /// The body runs in a new scope holding only the arguments, which is
+    /// discarded when the call returns.
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<Option<Value>, RuntimeError> {
+        let function = match self.functions.get(name) {
+            Some(function) => Rc::clone(function),
+            None => return self.call_builtin(name, args).map(Some),
+        };
+        if args.len() != function.params.len() {
+            return Err(RuntimeError::WrongArgumentCount {
+                name: name.to_string(),
+                expected: function.params.len(),
+                found: args.len(),
+            });
+        }
+
+        let mut scope = HashMap::new();
+        for (param, arg) in function.params.iter().zip(args) {
+            let value = self.eval_expr(arg)?;
+            scope.insert(param.clone(), value);
+        }
+        if self.depth == self.max_depth {
+            return Err(RuntimeError::RecursionLimit);
+        }
+        let caller = mem::replace(&mut self.env, vec![scope]);
+        self.depth += 1;
+        let result = self.exec_block(&function.body);
+        self.depth -= 1;
+        self.env = caller;
+        match result? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    /// Calls the builtin function `name`, used when no `func` of that name
+    /// has been defined.
+    fn call_builtin(&mut self, name: &str, args: &[Expr]) -> Result<Value, RuntimeError> {
+        let builtin: fn(Vec<Value>) -> Result<Value, RuntimeError> = match name {
+            "len" => len,
+            _ => return Err(RuntimeError::UndefinedFunction(name.to_string())),
+        };
+        let args = args
+            .iter()
+            .map(|arg| self.eval_expr(arg))
+            .collect::<Result<_, _>>()?;
+        builtin(args)
+    }
+}
+
+/// Checks that `value` has the type `ty` that the variable `name` was
+/// declared with.
+fn check_type(name: &str, ty: Type, value: &Value) -> Result<(), RuntimeError> {
+    let matches = matches!(
+        (value, ty),
+        (&Value::Integer(_), Type::Int)
+            | (&Value::Boolean(_), Type::Bool)
+            | (&Value::String(_), Type::String)
+            | (&Value::Array(_), Type::Array)
+    );
+    if matches {
+        return Ok(());
+    }
+    Err(RuntimeError::TypeError(format!(
+        "cannot bind {} to `{}` of type {}",
+        value.type_name(),
+        name,
+        ty
+    )))
+}
+
+/// Renders an integer in the base `format` names, with a leading `-` if it
+/// is negative.
+fn format_integer(value: Value, format: Format) -> Result<Value, RuntimeError> {
+    let n = match value {
+        Value::Integer(n) => n,
+        other => {
+            return Err(RuntimeError::TypeError(format!(
+                "cannot format {} as a number",
+                other.type_name()
+            )))
+        }
+    };
+    let sign = if n < 0 { "-" } else { "" };
+    let magnitude = n.unsigned_abs();
+    let digits = match format {
+        Format::Hex => format!("{:x}", magnitude),
+        Format::Binary => format!("{:b}", magnitude),
+    };
+    Ok(Value::String(format!("{}{}", sign, digits)))
+}
+
+/// Returns the value a variable of type `ty` holds before anything is stored
+/// in it.
+fn zero_value(ty: Type) -> Value {
+    match ty {
+        Type::Int => Value::Integer(0),
+        Type::Bool => Value::Boolean(false),
+        Type::String => Value::String(String::new()),
+        Type::Array => Value::Array(Vec::new()),
+    }
+}
+
+/// Makes an array of `size` zero values of type `element`.
+fn zeroed_array(size: Value, element: Type) -> Result<Value, RuntimeError> {
+    match size {
+        Value::Integer(n) if n > 0 && n <= MAX_ARRAY_LEN => {
+            Ok(Value::Array(vec![zero_value(element); n as usize]))
+        }
+        Value::Integer(n) => Err(RuntimeError::InvalidArraySize(n)),
+        other => Err(RuntimeError::TypeError(format!(
+            "array size must be an integer, not {}",
+            other.type_name()
+        ))),
+    }
+}
+
+/// `len(value)` counts the elements of an array or the chars of a string.
+fn len(mut args: Vec<Value>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        return Err(RuntimeError::WrongArgumentCount {
+            name: "len".to_string(),
+            expected: 1,
+            found: args.len(),
+        });
+    }
+    match args.pop().unwrap() {
+        Value::Array(elements) => Ok(Value::Integer(elements.len() as i32)),
+        Value::String(s) => Ok(Value::Integer(s.chars().count() as i32)),
+        other => Err(RuntimeError::TypeError(format!(
+            "cannot take the length of {}",
+            other.type_name()
+        ))),
+    }
+}
+
+/// Returns the values a `for` loop over `value` visits.
+fn elements(value: Value) -> Result<Vec<Value>, RuntimeError> {
+    match value {
+        Value::Array(elements) => Ok(elements),
+        other => Err(RuntimeError::TypeError(format!(
+            "cannot iterate over {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn element_at(target: Value, index: Value) -> Result<Value, RuntimeError> {
+    match (target, index) {
+        (Value::Array(mut elements), Value::Integer(i)) => {
+            if i < 0 || i as usize >= elements.len() {
+                return Err(RuntimeError::IndexOutOfBounds {
+                    index: i,
+                    len: elements.len(),
+                });
+            }
+            Ok(elements.swap_remove(i as usize))
+        }
+        (Value::Array(_), index) => Err(RuntimeError::TypeError(format!(
+            "cannot index an array with {}",
+            index.type_name()
+        ))),
+        (target, _) => Err(RuntimeError::TypeError(format!(
+            "cannot index {}",
+            target.type_name()
+        ))),
+    }
+}
+
+fn unary(op: UnaryOp, operand: Value) -> Result<Value, RuntimeError> {
+    let type_name = operand.type_name();
+    let result = match operand.into_scalar() {
+        Some(operand) => ops::unary(op, operand),
+        None => Err(OpError::TypeMismatch),
+    };
+    result
+        .map(Value::from)
+        .map_err(|err| op_error(err, || format!("cannot apply `{}` to {}", op, type_name)))
+}
+
+/// Returns the result of `op` if `lhs` alone decides it, in which case the
+/// right operand is not evaluated.
+fn short_circuit(op: BinaryOp, lhs: &Value) -> Option<Value> {
+    match (op, lhs) {
+        (BinaryOp::And, &Value::Boolean(false)) => Some(Value::Boolean(false)),
+        (BinaryOp::Or, &Value::Boolean(true)) => Some(Value::Boolean(true)),
+        _ => None,
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    let type_names = (lhs.type_name(), rhs.type_name());
+    let result = match (lhs.into_scalar(), rhs.into_scalar()) {
+        (Some(lhs), Some(rhs)) => ops::binary(op, lhs, rhs),
+        _ => Err(OpError::TypeMismatch),
+    };
+    result.map(Value::from).map_err(|err| {
+        op_error(err, || {
+            format!(
+                "cannot apply `{}` to {} and {}",
+                op, type_names.0, type_names.1
+            )
+        })
+    })
+}
+
+/// Converts the error from applying an operator, describing a type mismatch
+/// with `mismatch`.
+fn op_error(err: OpError, mismatch: impl FnOnce() -> String) -> RuntimeError {
+    match err {
+        OpError::TypeMismatch => RuntimeError::TypeError(mismatch()),
+        OpError::Overflow => RuntimeError::Overflow,
+        OpError::DivisionByZero => RuntimeError::DivisionByZero,
+        OpError::NegativeExponent => RuntimeError::NegativeExponent,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Interpreter, RuntimeError};
+    use parser;
+
+    /// Parses and runs `source`, returning the result along with everything
+    /// it printed.
+    fn run(source: &str) -> (Result<(), RuntimeError>, String) {
+        let stmts = parser::parse(source).unwrap();
+        let mut out = Vec::new();
+        let result = Interpreter::new(&mut out).eval(&stmts);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn truthiness() {
+        use super::Value;
+
+        assert!(Value::Boolean(true).is_truthy());
+        assert!(!Value::Boolean(false).is_truthy());
+        assert!(Value::Integer(-1).is_truthy());
+        assert!(!Value::Integer(0).is_truthy());
+        assert!(Value::String("0".to_string()).is_truthy());
+        assert!(!Value::String(String::new()).is_truthy());
+        assert!(Value::Array(vec![Value::Boolean(false)]).is_truthy());
+        assert!(!Value::Array(vec![]).is_truthy());
+
+        let (result, out) = run("let count := 3; while count { print count; count -= 1; } \
+             if \"\" { print 1; } else { print 2; } print if [0] { 3 } else { 4 };");
+        assert!(result.is_ok());
+        assert_eq!(out, "3\n2\n1\n2\n3\n");
+    }
+
+    #[test]
+    fn display() {
+        use super::Value;
+
+        assert_eq!(Value::Integer(-3).to_string(), "-3");
+        assert_eq!(Value::Boolean(false).to_string(), "false");
+        assert_eq!(Value::String("a \"b\"".to_string()).to_string(), "a \"b\"");
+        assert_eq!(
+            Value::Array(vec![
+                Value::Integer(1),
+                Value::String("x".to_string()),
+                Value::Array(vec![]),
+            ])
+            .to_string(),
+            "[1, x, []]"
+        );
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let (result, out) = run("let x := 5; x += 2; print x; x -= 1; x *= 3; x /= 4; print x;");
+        assert!(result.is_ok());
+        assert_eq!(out, "7\n4\n");
+
+        let (result, _) = run("y += 2;");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(ref name)) if name == "y"));
+    }
+
+    #[test]
+    fn print_list() {
+        let (result, out) = run("print 1, 2, 3; print \"x =\", 1 + 1;");
+        assert!(result.is_ok());
+        assert_eq!(out, "1 2 3\nx = 2\n");
+
+        let (result, out) = run("print 1, y;");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(_))));
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn while_loop() {
+        let (result, out) = run("let x := 0; while x < 3 { print x; x := x + 1; }");
+
+        assert!(result.is_ok());
+        assert_eq!(out, "0\n1\n2\n");
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (result, out) = run("print 1 + 2 * 3; print (1 + 2) * 3; print 7 / 2; print 2 - 5;");
+
+        assert!(result.is_ok());
+        assert_eq!(out, "7\n9\n3\n-3\n");
+    }
+
+    #[test]
+    fn string_concatenation() {
+        let (result, out) = run("let s := \"foo\" + \"bar\"; print s + \"\";");
+        assert!(result.is_ok());
+        assert_eq!(out, "foobar\n");
+
+        let (result, _) = run("print \"a\" + 1;");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot apply `+` to string and integer"));
+    }
+
+    #[test]
+    fn negation() {
+        let (result, out) = run("let a := 2; print -a; print -(a - 5); print 3 - -2;");
+
+        assert!(result.is_ok());
+        assert_eq!(out, "-2\n3\n5\n");
+
+        let (result, _) = run("print -(1 < 2);");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot apply `-` to boolean"));
+    }
+
+    #[test]
+    fn comparisons_and_if() {
+        let (result, out) = run("let x := 5; if x >= 5 { print x == 5; } if x < 5 { print 0; }");
+
+        assert!(result.is_ok());
+        assert_eq!(out, "true\n");
+    }
+
+    #[test]
+    fn if_else() {
+        let (result, out) =
+            run("let x := 1; while x > -2 { if x < 0 { print 1; } else { print 2; } x := x - 1; }");
+
+        assert!(result.is_ok());
+        assert_eq!(out, "2\n2\n1\n");
+    }
+
+    #[test]
+    fn booleans() {
+        let (result, out) = run("let done := false; print done == false; if true { print true; }");
+
+        assert!(result.is_ok());
+        assert_eq!(out, "true\ntrue\n");
+    }
+
+    #[test]
+    fn logical_operators() {
+        let (result, out) =
+            run("print 1 < 2 and 2 < 3; print false or 1 > 2; print false or true and true;");
+        assert!(result.is_ok());
+        assert_eq!(out, "true\nfalse\ntrue\n");
+
+        let (result, _) = run("print true and 1;");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot apply `and` to boolean and integer"));
+
+        let (result, _) = run("print 1 or true;");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot apply `or` to integer and boolean"));
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        let (result, out) = run("print 5 and 3, 5 or 3, -1 and 12, 0 or 0; print true and false;");
+        assert!(result.is_ok());
+        assert_eq!(out, "1 7 12 0\nfalse\n");
+    }
+
+    #[test]
+    fn not() {
+        let (result, out) =
+            run("let a := true; let b := false; print not true; print not (a and b);");
+        assert!(result.is_ok());
+        assert_eq!(out, "false\ntrue\n");
+
+        let (result, _) = run("print not 5;");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot apply `not` to integer"));
+    }
+
+    #[test]
+    fn short_circuit() {
+        // Evaluating `missing()` would fail, as no such function exists.
+        let (result, out) = run("print false and missing(); print true or missing();");
+        assert!(result.is_ok());
+        assert_eq!(out, "false\ntrue\n");
+
+        let (result, _) = run("print true and missing();");
+        assert!(matches!(result, Err(RuntimeError::UndefinedFunction(_))));
+    }
+
+    #[test]
+    fn state_persists_between_evals() {
+        use super::Value;
+
+        let mut out = Vec::new();
+        let mut interpreter = Interpreter::new(&mut out);
+        interpreter
+            .eval(&parser::parse("let x := 1;").unwrap())
+            .unwrap();
+        interpreter
+            .eval(&parser::parse("x := x + 1; print x;").unwrap())
+            .unwrap();
+
+        assert_eq!(interpreter.lookup("x"), Some(&Value::Integer(2)));
+        assert_eq!(out, b"2\n");
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        use super::Value;
+
+        let mut out = Vec::new();
+        let mut interpreter = Interpreter::new(&mut out);
+        interpreter
+            .eval(&parser::parse("let x := 1;").unwrap())
+            .unwrap();
+
+        let snapshot = interpreter.snapshot();
+        let stmts = parser::parse("x := 2; let y := 3; func f() { } print y / 0; x := 4;").unwrap();
+        assert!(matches!(
+            interpreter.eval(&stmts),
+            Err(RuntimeError::DivisionByZero)
+        ));
+        assert_eq!(interpreter.lookup("x"), Some(&Value::Integer(2)));
+
+        interpreter.restore(snapshot);
+        assert_eq!(interpreter.lookup("x"), Some(&Value::Integer(1)));
+        assert_eq!(interpreter.lookup("y"), None);
+        assert!(!interpreter.functions.contains_key("f"));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let (result, out) = run("print 1; print 1 / (2 - 2); print 2;");
+
+        assert!(matches!(result, Err(RuntimeError::DivisionByZero)));
+        assert_eq!(out, "1\n");
+    }
+
+    #[test]
+    fn comparison_operators() {
+        let (result, out) = run(
+            "print 1 == 1, 1 == 2; print 1 <> 2, 1 <> 1; print 1 < 2, 2 < 1; \
+             print 2 <= 2, 3 <= 2; print 2 > 1, 1 > 2; print 2 >= 2, 1 >= 2;",
+        );
+        assert!(result.is_ok());
+        assert_eq!(
+            out,
+            "true false\ntrue false\ntrue false\ntrue false\ntrue false\ntrue false\n"
+        );
+
+        let (result, out) = run("print true == true, true == false, true <> false; \
+             print \"a\" == \"a\", \"a\" == \"b\", \"a\" <> \"b\";");
+        assert!(result.is_ok());
+        assert_eq!(out, "true false true\ntrue false true\n");
+
+        let (result, _) = run("print 1 < \"a\";");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot apply `<` to integer and string"));
+    }
+
+    #[test]
+    fn remainder() {
+        let (result, out) = run("print 7 % 3; print 6 % 3;");
+        assert!(result.is_ok());
+        assert_eq!(out, "1\n0\n");
+
+        let (result, out) = run("print (-7) % 3; print 7 % (-3); print -7 % -3;");
+        assert!(result.is_ok());
+        assert_eq!(out, "2\n1\n2\n");
+
+        let (result, _) = run("let x := 0; print 7 % x;");
+        assert!(matches!(result, Err(RuntimeError::DivisionByZero)));
+    }
+
+    #[test]
+    fn power() {
+        let (result, out) =
+            run("print 2 ** 10; print -2 ** 2; print (-2) ** 3; print 2 ** 3 ** 2;");
+        assert!(result.is_ok());
+        assert_eq!(out, "1024\n-4\n-8\n512\n");
+
+        let (result, _) = run("print 2 ** 31;");
+        assert!(matches!(result, Err(RuntimeError::Overflow)));
+
+        let (result, _) = run("print 2 ** -1;");
+        assert!(matches!(result, Err(RuntimeError::NegativeExponent)));
+    }
+
+    #[test]
+    fn arrays() {
+        let (result, out) = run("let a := [1, 2 + 3, [true]]; print a; print a[1]; print a[2][0];");
+        assert!(result.is_ok());
+        assert_eq!(out, "[1, 5, [true]]\n5\ntrue\n");
+
+        let (result, _) = run("let a := [1, 2]; print a[2];");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::IndexOutOfBounds { index: 2, len: 2 })
+        ));
+
+        let (result, _) = run("print [][-1];");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::IndexOutOfBounds { index: -1, len: 0 })
+        ));
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let (result, out) = run("let x := 0; while true { print x; if x == 2 { break; } x += 1; }");
+        assert!(result.is_ok());
+        assert_eq!(out, "0\n1\n2\n");
+
+        let (result, out) = run(
+            "for x in [1, 2, 3, 4] { if x % 2 == 0 { continue; } print x; } \
+             for x in [1, 2] { for y in [1, 2] { if y == 2 { break; } print x, y; } }",
+        );
+        assert!(result.is_ok());
+        assert_eq!(out, "1\n3\n1 1\n2 1\n");
+    }
+
+    #[test]
+    fn block_scopes() {
+        let (result, out) = run("let x := 1; \
+             if true { let x := 2; print x; x := 3; print x; } \
+             print x; \
+             while x < 3 { let y := x; x := x + 1; print y; } \
+             for i in [5] { let x := i; } \
+             print x;");
+
+        assert!(result.is_ok());
+        assert_eq!(out, "2\n3\n1\n1\n2\n3\n");
+
+        let (result, out) = run("if true { let y := 1; } print y;");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(ref name)) if name == "y"));
+        assert_eq!(out, "");
+
+        let (result, out) = run("let n := 0; for i in [1, 2] { n := n + i; } print n; print i;");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(ref name)) if name == "i"));
+        assert_eq!(out, "3\n");
+    }
+
+    #[test]
+    fn array_declarations() {
+        let (result, out) = run("let n := 2; array[n + 1] int xs; array[2] string ss; \
+             array[1] bool bs; array[1] array grid; \
+             print xs, xs[2], len(ss), ss[0] + \"!\", bs[0], grid;");
+        assert!(result.is_ok());
+        assert_eq!(out, "[0, 0, 0] 0 2 ! false [[]]\n");
+
+        let (result, _) = run("array[0] int xs;");
+        assert!(matches!(result, Err(RuntimeError::InvalidArraySize(0))));
+
+        let (result, _) = run("array[-3] int xs;");
+        assert!(matches!(result, Err(RuntimeError::InvalidArraySize(-3))));
+
+        let (result, _) = run("array[2147483647] int xs;");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::InvalidArraySize(2147483647))
+        ));
+
+        let (result, _) = run("array[2] int xs; print xs[2];");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::IndexOutOfBounds { index: 2, len: 2 })
+        ));
+
+        let (result, _) = run("array[true] int xs;");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "array size must be an integer, not boolean"));
+    }
+
+    #[test]
+    fn if_expressions() {
+        let (result, out) = run("let x := 5; \
+             let y := if x > 0 { 1 } else { -1 }; \
+             let sign := if x < 0 { -1 } else if x == 0 { 0 } else { print \"pos\"; 1 }; \
+             let z := { let x := 10; x * 2 } + 1; \
+             print y, sign, z, x;");
+        assert!(result.is_ok());
+        assert_eq!(out, "pos\n1 1 21 5\n");
+
+        let (result, out) = run("print if 0 { 1 } else { 2 };");
+        assert!(result.is_ok());
+        assert_eq!(out, "2\n");
+    }
+
+    #[test]
+    fn print_formats() {
+        let (result, out) = run("print 255: hex; print 255; \
+             print 5: bin, -255: hex, -2147483647 - 1: hex, 0: bin;");
+        assert!(result.is_ok());
+        assert_eq!(out, "ff\n255\n101 -ff -80000000 0\n");
+
+        let (result, _) = run("print \"x\": hex;");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot format string as a number"));
+    }
+
+    #[test]
+    fn for_each() {
+        let (result, out) =
+            run("let total := 0; for x in [1, 2, 3] { total := total + x; } print total;");
+
+        assert!(result.is_ok());
+        assert_eq!(out, "6\n");
+    }
+
+    #[test]
+    fn functions() {
+        let (result, out) = run("func hello() { print 1; } hello(); hello();");
+        assert!(result.is_ok());
+        assert_eq!(out, "1\n1\n");
+
+        let (result, out) = run(
+            "let a := 10; func show(a, b) { print a - b; a := 0; } show(5, 3); show(a, 1); print a;",
+        );
+        assert!(result.is_ok());
+        assert_eq!(out, "2\n9\n10\n");
+    }
+
+    #[test]
+    fn returns() {
+        let (result, out) = run("func square(x) { return x * x; } \
+             func fact(n) { if n < 2 { return 1; } return n * fact(n - 1); } \
+             print square(7), fact(10);");
+        assert!(result.is_ok());
+        assert_eq!(out, "49 3628800\n");
+
+        // A return leaves any loops it is in, and a bare one produces nothing.
+        let (result, out) = run(
+            "func find(xs, x) { let i := 0; for y in xs { if y == x { return i; } i += 1; } return -1; } \
+             func early() { while true { print 1; return; } print 2; } \
+             print find([4, 5, 6], 5), find([], 1); early();",
+        );
+        assert!(result.is_ok());
+        assert_eq!(out, "1 -1\n1\n");
+
+        let (result, _) = run("func f() { return; } print f();");
+        assert!(matches!(result, Err(RuntimeError::TypeError(_))));
+
+        let (result, out) = run("print 1; return 2; print 3;");
+        assert!(matches!(result, Err(RuntimeError::ReturnOutsideFunction)));
+        assert_eq!(out, "1\n");
+    }
+
+    #[test]
+    fn assertions() {
+        let (result, out) = run("let x := 2; assert x == 2; assert x, \"unused\"; print x;");
+        assert!(result.is_ok());
+        assert_eq!(out, "2\n");
+
+        let (result, out) =
+            run("let s := \"two\";\nprint 1;\n  assert s == \"three\", \"s is \" + s; print 2;");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::AssertionFailed {
+                message: Some(ref message),
+                line: 3,
+                col: 2,
+            }) if message == "s is two"
+        ));
+        assert_eq!(out, "1\n");
+
+        let (result, _) = run("assert 1 > 2;");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::AssertionFailed { message: None, .. })
+        ));
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let (result, out) = run("func f() { f(); } print 1; f();");
+        assert!(matches!(result, Err(RuntimeError::RecursionLimit)));
+        assert_eq!(out, "1\n");
+
+        let (result, out) = run(
+            "func count(n) { if n > 0 { count(n - 1); } else { print n; } } \
+             count(199);",
+        );
+        assert!(result.is_ok());
+        assert_eq!(out, "0\n");
+
+        let mut out = Vec::new();
+        let mut interpreter = Interpreter::new(&mut out);
+        interpreter.set_max_depth(3);
+        let stmts = parser::parse("func f(n) { print n; f(n + 1); } f(1);").unwrap();
+        assert!(matches!(
+            interpreter.eval(&stmts),
+            Err(RuntimeError::RecursionLimit)
+        ));
+        let stmts = parser::parse("f(10);").unwrap();
+        assert!(matches!(
+            interpreter.eval(&stmts),
+            Err(RuntimeError::RecursionLimit)
+        ));
+        assert_eq!(out, b"1\n2\n3\n10\n11\n12\n");
+    }
+
+    #[test]
+    fn len() {
+        let (result, out) =
+            run("print len([1, 2, 3]); print len(\"abcd\"); print len(\"é\"); print len([]);");
+        assert!(result.is_ok());
+        assert_eq!(out, "3\n4\n1\n0\n");
+
+        let (result, _) = run("print len(5);");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot take the length of integer"));
+
+        let (result, _) = run("print len(true);");
+        assert!(matches!(result, Err(RuntimeError::TypeError(_))));
+
+        let (result, _) = run("print len();");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::WrongArgumentCount {
+                expected: 1,
+                found: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn call_errors() {
+        let (result, _) = run("func f(x) { } f(1, 2);");
+        assert!(matches!(result, Err(RuntimeError::WrongArgumentCount {
+            ref name,
+            expected: 1,
+            found: 2,
+        }) if name == "f"));
+
+        let (result, _) = run("g();");
+        assert!(matches!(result, Err(RuntimeError::UndefinedFunction(ref name)) if name == "g"));
+
+        let (result, _) = run("func f() { } print f();");
+        assert!(matches!(result, Err(RuntimeError::TypeError(_))));
+
+        let (result, _) = run("let x := 1; func f() { print x; } f();");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(_))));
+    }
+
+    #[test]
+    fn overflow() {
+        let (result, _) = run("let x := 2147483647; x := x + 1;");
+
+        assert!(matches!(result, Err(RuntimeError::Overflow)));
+    }
+
+    #[test]
+    fn undefined_variable() {
+        let (result, _) = run("print y;");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(ref name)) if name == "y"));
+
+        let (result, _) = run("y := 1;");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(ref name)) if name == "y"));
+    }
+
+    #[test]
+    fn type_errors() {
+        let (result, _) = run("print 1 + (1 < 2);");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot apply `+` to integer and boolean"));
+
+        let (result, _) = run("while not 1 { }");
+        assert!(matches!(result, Err(RuntimeError::TypeError(_))));
+
+        let (result, _) = run("print true * \"x\";");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot apply `*` to boolean and string"));
+
+        let (result, _) = run("print 1[0];");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot index integer"));
+
+        let (result, _) = run("for i in 5 { print i; }");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot iterate over integer"));
+    }
+
+    #[test]
+    fn type_annotations() {
+        let (result, out) = run("let x: int := 5; let s: string := \"a\"; \
+             let b: bool := x > 1; let xs: array := [x]; print x, s, b, xs;");
+        assert!(result.is_ok());
+        assert_eq!(out, "5 a true [5]\n");
+
+        let (result, _) = run("let x: bool := 5;");
+        assert!(matches!(result, Err(RuntimeError::TypeError(ref message))
+            if message == "cannot bind integer to `x` of type bool"));
+    }
+}
@@ -0,0 +1,20 @@
+#[macro_use]
+extern crate lazy_static;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "unicode-xid")]
+extern crate unicode_xid;
+#[cfg(test)]
+#[macro_use]
+extern crate proptest;
+
+pub mod diagnostics;
+pub mod interpreter;
+pub mod ops;
+pub mod parser;
+pub mod repl;
+pub mod semantic_tokens;
+pub mod tokenizer;
@@ -1,4 +0,0 @@
-#[macro_use]
-extern crate lazy_static;
-
-pub mod tokenizer;
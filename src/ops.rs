@@ -0,0 +1,217 @@
+//! The unary and binary operators, and what they compute from booleans,
+//! integers and strings. Both the interpreter and constant folding in the
+//! parser apply operators through this module, so they always agree.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+/// Formats an operator as it is written in source.
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "not",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BinaryOp {
+    // Arithmetic
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// The Euclidean remainder, which is never negative.
+    Rem,
+    Pow,
+
+    // Comparison
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+
+    // Logical
+    And,
+    Or,
+}
+
+/// Formats an operator as it is written in source.
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Pow => "**",
+
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "<>",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+        })
+    }
+}
+
+impl BinaryOp {
+    /// Returns whether the operator compares its operands.
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge
+        )
+    }
+}
+
+/// An operand an operator can be applied to by `unary` and `binary`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scalar {
+    Boolean(bool),
+    Integer(i32),
+    String(String),
+}
+
+/// Why an operator could not be applied to its operands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpError {
+    /// The operator does not apply to operands of these types.
+    TypeMismatch,
+    /// Integer arithmetic produced a result that does not fit in an `i32`.
+    Overflow,
+    /// An integer was divided by zero, or its remainder taken.
+    DivisionByZero,
+    /// An integer was raised to a negative power.
+    NegativeExponent,
+}
+
+pub fn unary(op: UnaryOp, operand: Scalar) -> Result<Scalar, OpError> {
+    match (op, operand) {
+        (UnaryOp::Neg, Scalar::Integer(n)) => n
+            .checked_neg()
+            .map(Scalar::Integer)
+            .ok_or(OpError::Overflow),
+        (UnaryOp::Not, Scalar::Boolean(b)) => Ok(Scalar::Boolean(!b)),
+        _ => Err(OpError::TypeMismatch),
+    }
+}
+
+/// Applies `op` to both operands. `and` and `or` do not short-circuit here;
+/// a caller that evaluates operands lazily must check the left one first.
+pub fn binary(op: BinaryOp, lhs: Scalar, rhs: Scalar) -> Result<Scalar, OpError> {
+    match (lhs, rhs) {
+        (Scalar::Integer(a), Scalar::Integer(b)) => integer_binary(op, a, b),
+        (Scalar::String(a), Scalar::String(b)) if op == BinaryOp::Add => Ok(Scalar::String(a + &b)),
+        (Scalar::String(a), Scalar::String(b)) if op == BinaryOp::Eq => Ok(Scalar::Boolean(a == b)),
+        (Scalar::String(a), Scalar::String(b)) if op == BinaryOp::Ne => Ok(Scalar::Boolean(a != b)),
+        (Scalar::Boolean(a), Scalar::Boolean(b)) if op == BinaryOp::Eq => {
+            Ok(Scalar::Boolean(a == b))
+        }
+        (Scalar::Boolean(a), Scalar::Boolean(b)) if op == BinaryOp::Ne => {
+            Ok(Scalar::Boolean(a != b))
+        }
+        (Scalar::Boolean(a), Scalar::Boolean(b)) if op == BinaryOp::And => {
+            Ok(Scalar::Boolean(a && b))
+        }
+        (Scalar::Boolean(a), Scalar::Boolean(b)) if op == BinaryOp::Or => {
+            Ok(Scalar::Boolean(a || b))
+        }
+        _ => Err(OpError::TypeMismatch),
+    }
+}
+
+fn integer_binary(op: BinaryOp, a: i32, b: i32) -> Result<Scalar, OpError> {
+    let arithmetic = match op {
+        BinaryOp::Add => a.checked_add(b),
+        BinaryOp::Sub => a.checked_sub(b),
+        BinaryOp::Mul => a.checked_mul(b),
+        BinaryOp::Div if b == 0 => return Err(OpError::DivisionByZero),
+        BinaryOp::Div => a.checked_div(b),
+        BinaryOp::Rem if b == 0 => return Err(OpError::DivisionByZero),
+        // The remainder is Euclidean, so it is never negative.
+        BinaryOp::Rem => a.checked_rem_euclid(b),
+        BinaryOp::Pow if b < 0 => return Err(OpError::NegativeExponent),
+        BinaryOp::Pow => a.checked_pow(b as u32),
+
+        BinaryOp::Eq => return Ok(Scalar::Boolean(a == b)),
+        BinaryOp::Ne => return Ok(Scalar::Boolean(a != b)),
+        BinaryOp::Lt => return Ok(Scalar::Boolean(a < b)),
+        BinaryOp::Le => return Ok(Scalar::Boolean(a <= b)),
+        BinaryOp::Gt => return Ok(Scalar::Boolean(a > b)),
+        BinaryOp::Ge => return Ok(Scalar::Boolean(a >= b)),
+
+        // On integers, `and` and `or` are bitwise.
+        BinaryOp::And => return Ok(Scalar::Integer(a & b)),
+        BinaryOp::Or => return Ok(Scalar::Integer(a | b)),
+    };
+    arithmetic.map(Scalar::Integer).ok_or(OpError::Overflow)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{binary, unary, BinaryOp, OpError, Scalar, UnaryOp};
+
+    #[test]
+    fn integers() {
+        assert_eq!(
+            binary(BinaryOp::Rem, Scalar::Integer(-7), Scalar::Integer(3)),
+            Ok(Scalar::Integer(2))
+        );
+        assert_eq!(
+            binary(BinaryOp::Lt, Scalar::Integer(1), Scalar::Integer(2)),
+            Ok(Scalar::Boolean(true))
+        );
+        assert_eq!(
+            binary(BinaryOp::Div, Scalar::Integer(1), Scalar::Integer(0)),
+            Err(OpError::DivisionByZero)
+        );
+        assert_eq!(
+            binary(BinaryOp::Pow, Scalar::Integer(2), Scalar::Integer(-1)),
+            Err(OpError::NegativeExponent)
+        );
+        assert_eq!(
+            unary(UnaryOp::Neg, Scalar::Integer(i32::MIN)),
+            Err(OpError::Overflow)
+        );
+    }
+
+    #[test]
+    fn strings_and_booleans() {
+        assert_eq!(
+            binary(
+                BinaryOp::Add,
+                Scalar::String("a".to_string()),
+                Scalar::String("b".to_string())
+            ),
+            Ok(Scalar::String("ab".to_string()))
+        );
+        assert_eq!(
+            binary(BinaryOp::And, Scalar::Boolean(true), Scalar::Boolean(false)),
+            Ok(Scalar::Boolean(false))
+        );
+        assert_eq!(
+            binary(BinaryOp::Add, Scalar::Integer(1), Scalar::Boolean(true)),
+            Err(OpError::TypeMismatch)
+        );
+        assert_eq!(
+            unary(UnaryOp::Not, Scalar::Integer(1)),
+            Err(OpError::TypeMismatch)
+        );
+    }
+}
@@ -0,0 +1,2754 @@
+//! Parsing of ALAN source into a syntax tree.
+//!
+//! `Parser` is a recursive-descent parser over the tokens of a `Tokenizer`,
+//! following the grammar in the README:
+//!
+//! ```
+//! use rust_tmp::parser::{self, BinaryOp, Expr, Stmt};
+//! use rust_tmp::tokenizer::Span;
+//!
+//! let stmts = parser::parse("print 1 + x;").unwrap();
+//! assert_eq!(stmts, vec![Stmt::Print(vec![Expr::Binary {
+//!     op: BinaryOp::Add,
+//!     lhs: Box::new(Expr::Integer(1)),
+//!     rhs: Box::new(Expr::Name("x".to_string())),
+//!     span: Span { start: 6, end: 11, line: 1, col: 6 },
+//! }])]);
+//! ```
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use ops::{self, Scalar};
+use tokenizer::{Span, Spanned, Token, Tokenizer, WithSpans};
+
+pub use ops::{BinaryOp, UnaryOp};
+
+/// An expression, which evaluates to a value. Expressions made of others
+/// carry the span of source they were parsed from, from their first token
+/// to their last.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Expr {
+    Boolean(bool),
+    Integer(i32),
+    String(String),
+    Name(String),
+    /// `[elements]` builds a new array.
+    ArrayLiteral(Vec<Expr>),
+    /// `target[index]` reads one element of an array.
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    /// `name(args)` calls a function.
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+    },
+    /// `{ stmts value }` runs `stmts` in a new scope, then evaluates to
+    /// `value`.
+    Block {
+        stmts: Vec<Stmt>,
+        value: Box<Expr>,
+        span: Span,
+    },
+    /// `value: format`, allowed only as an argument to `print`, renders an
+    /// integer as a string in another base.
+    Formatted {
+        value: Box<Expr>,
+        format: Format,
+    },
+    /// `if cond { ... } else { ... }` evaluates to one of its branches, each
+    /// a block or, after `else`, another `if`.
+    If {
+        cond: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+        span: Span,
+    },
+}
+
+/// A way of printing an integer, named after a `:` in a `print` argument.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Format {
+    Hex,
+    Binary,
+}
+
+impl Format {
+    /// Returns the format spelled `name`, or `None` if `name` is not a
+    /// format.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "hex" => Some(Format::Hex),
+            "bin" => Some(Format::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// A type named in a `let` annotation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Type {
+    Int,
+    Bool,
+    String,
+    Array,
+}
+
+impl Type {
+    /// Returns the type spelled `name`, or `None` if `name` is not a type.
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "bool" => Some(Type::Bool),
+            "string" => Some(Type::String),
+            "array" => Some(Type::Array),
+            _ => None,
+        }
+    }
+}
+
+/// Formats a type as it is written in source.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Type::Int => "int",
+            Type::Bool => "bool",
+            Type::String => "string",
+            Type::Array => "array",
+        })
+    }
+}
+
+/// A statement, which is executed for its effect.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Stmt {
+    /// `let name := value;` introduces a variable. With an annotation, as in
+    /// `let name: int := value;`, the value must have type `ty`.
+    Let {
+        name: String,
+        ty: Option<Type>,
+        value: Expr,
+    },
+    /// `array[size] element name;` introduces a variable holding an array of
+    /// `size` elements of type `element`, each the zero value of that type.
+    ArrayDecl {
+        name: String,
+        size: Expr,
+        element: Type,
+    },
+    /// `name := value;` updates an existing variable.
+    Assign {
+        name: String,
+        value: Expr,
+    },
+    /// `print args;` writes its arguments on one line, separated by spaces.
+    Print(Vec<Expr>),
+    While {
+        cond: Expr,
+        body: Vec<Stmt>,
+    },
+    If {
+        cond: Expr,
+        then_branch: Vec<Stmt>,
+        else_branch: Option<Vec<Stmt>>,
+    },
+    /// `func name(params) { body }` defines a function.
+    FuncDef {
+        name: String,
+        params: Vec<String>,
+        body: Vec<Stmt>,
+    },
+    /// `break;` leaves the innermost loop.
+    Break,
+    /// `continue;` skips to the next iteration of the innermost loop.
+    Continue,
+    /// `return value;` ends the function being run, producing `value` if
+    /// there is one.
+    Return(Option<Expr>),
+    /// `assert cond;` stops the program with an error if `cond` is falsy.
+    /// With a message, as in `assert cond, "message";`, the error carries
+    /// the value of `message`. `span` is that of the `assert` keyword.
+    Assert {
+        cond: Expr,
+        message: Option<Expr>,
+        span: Span,
+    },
+    /// An expression evaluated only for its effects. Only calls may be used
+    /// this way.
+    Expr(Expr),
+    /// `for name in iterable { body }` runs `body` once for each element of
+    /// an array, with `name` bound to the element.
+    ForEach {
+        name: String,
+        iterable: Expr,
+        body: Vec<Stmt>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    /// A token that cannot appear at this point in the program.
+    UnexpectedToken { found: Token, span: Span },
+    /// A token other than the one that must come next, such as anything
+    /// but `;` at the end of a statement.
+    ExpectedToken {
+        expected: Token,
+        found: Token,
+        span: Span,
+    },
+    /// The input ended partway through a statement. The span is the empty
+    /// one just after the last token.
+    UnexpectedEof { span: Span },
+    /// A comparison used as the operand of another, as in `a < b < c`. The
+    /// span runs from the start of the first comparison to the end of the
+    /// second operator.
+    ChainedComparison { span: Span },
+    /// A `break` or `continue` that is not inside any loop of the function
+    /// it appears in.
+    OutsideLoop { found: Token, span: Span },
+    /// A `=` where an operator or the end of an expression should be, most
+    /// likely meant as `==` or `:=`.
+    SingleEquals { span: Span },
+}
+
+impl ParseError {
+    /// Returns the span of the token that caused the error, or where the
+    /// input ended.
+    pub fn span(&self) -> Span {
+        match *self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::ExpectedToken { span, .. }
+            | ParseError::UnexpectedEof { span }
+            | ParseError::ChainedComparison { span }
+            | ParseError::OutsideLoop { span, .. }
+            | ParseError::SingleEquals { span } => span,
+        }
+    }
+
+    /// Returns the description of the error, without its position.
+    pub fn message(&self) -> String {
+        match *self {
+            ParseError::UnexpectedToken { ref found, .. } => format!("unexpected '{}'", found),
+            ParseError::ExpectedToken {
+                ref expected,
+                ref found,
+                ..
+            } => format!("expected '{}', found '{}'", expected, found),
+            ParseError::UnexpectedEof { .. } => "unexpected end of input".to_string(),
+            ParseError::ChainedComparison { .. } => "comparisons cannot be chained".to_string(),
+            ParseError::OutsideLoop { ref found, .. } => {
+                format!("'{}' outside of a loop", found)
+            }
+            ParseError::SingleEquals { .. } => {
+                "unexpected '='; use '==' to compare or ':=' to assign".to_string()
+            }
+        }
+    }
+}
+
+/// Formats the error as `line:col: message`.
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let span = self.span();
+        write!(f, "{}:{}: {}", span.line, span.col, self.message())
+    }
+}
+
+impl Error for ParseError {}
+
+/// The tokens of a `Tokenizer` with their spans, and the lookahead and
+/// matching primitives a recursive-descent parser is built from.
+#[derive(Clone)]
+pub struct TokenStream<'a> {
+    tokens: WithSpans<'a>,
+    peeked: Option<Spanned<Token>>,
+    /// The empty span just after the last token read, other than
+    /// `Token::Eof`, which is where the input is taken to end.
+    end: Span,
+    /// The span of the last token consumed.
+    last: Span,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(tokenizer: Tokenizer<'a>) -> Self {
+        TokenStream {
+            end: tokenizer.cursor(),
+            last: tokenizer.cursor(),
+            tokens: tokenizer.with_spans(),
+            peeked: None,
+        }
+    }
+
+    /// Returns the error for input that ends where more was needed.
+    pub fn unexpected_eof(&self) -> ParseError {
+        ParseError::UnexpectedEof { span: self.end }
+    }
+
+    /// Returns the span of the last token consumed, or an empty span at the
+    /// start of the input if there is none.
+    pub fn last_span(&self) -> Span {
+        self.last
+    }
+
+    /// Reads the next token from the tokenizer, past any peeked one.
+    fn read(&mut self) -> Option<Spanned<Token>> {
+        let token = self.tokens.next()?;
+        if token.node != Token::Eof {
+            self.end = self.tokens.tokenizer().cursor();
+        }
+        Some(token)
+    }
+
+    /// Returns the next token without consuming it.
+    pub fn peek(&mut self) -> Option<&Token> {
+        self.peek_spanned().map(|spanned| &spanned.node)
+    }
+
+    /// Returns the next token and its span without consuming it.
+    pub fn peek_spanned(&mut self) -> Option<&Spanned<Token>> {
+        if self.peeked.is_none() {
+            self.peeked = self.read();
+        }
+        self.peeked.as_ref()
+    }
+
+    /// Consumes the next token along with its span.
+    pub fn next_spanned(&mut self) -> Option<Spanned<Token>> {
+        let token = match self.peeked.take() {
+            Some(token) => Some(token),
+            None => self.read(),
+        };
+        if let Some(ref token) = token {
+            self.last = token.span;
+        }
+        token
+    }
+
+    /// Returns whether the input is exhausted, whether or not the tokenizer
+    /// marks that with `Token::Eof`.
+    pub fn at_end(&mut self) -> bool {
+        match self.peek() {
+            None | Some(&Token::Eof) => true,
+            Some(_) => false,
+        }
+    }
+
+    /// Consumes the next token if it is `token`, returning whether it was.
+    pub fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.next_spanned();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes the next token, failing unless it is `token`.
+    pub fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        if self.at_end() {
+            return Err(self.unexpected_eof());
+        }
+        let next = self.next_spanned().ok_or_else(|| self.unexpected_eof())?;
+        if next.node == token {
+            Ok(())
+        } else {
+            Err(ParseError::ExpectedToken {
+                expected: token,
+                found: next.node,
+                span: next.span,
+            })
+        }
+    }
+}
+
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.next_spanned().map(|spanned| spanned.node)
+    }
+}
+
+pub struct Parser<'a> {
+    tokens: TokenStream<'a>,
+    /// Whether a line break can end a statement in place of a `;`.
+    newline_terminated: bool,
+    /// The number of loops enclosing the statement being parsed, within the
+    /// current function.
+    loop_depth: usize,
+    /// Whether the statement being parsed is inside a block expression,
+    /// within the current function.
+    in_block_expr: bool,
+    /// Whether to skip past statements with syntax errors rather than stop
+    /// at the first, as `parse_recovering` does.
+    recovering: bool,
+    /// The errors skipped past so far while recovering.
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Parser<'a> {
+    /// Creates a parser for the tokens of `tokenizer`. If the tokenizer
+    /// yields line breaks, as set with `Tokenizer::with_newlines`, then a
+    /// statement may end at the end of a line, the end of its block or the
+    /// end of input instead of with a `;`.
+    pub fn new(tokenizer: Tokenizer<'a>) -> Self {
+        Parser {
+            newline_terminated: tokenizer.emits_newlines(),
+            tokens: TokenStream::new(tokenizer),
+            loop_depth: 0,
+            in_block_expr: false,
+            recovering: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Parses statements until the input is exhausted.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut stmts = Vec::new();
+        self.parse_program(&mut stmts)?;
+        Ok(stmts)
+    }
+
+    /// Parses statements until the input is exhausted, collecting every
+    /// syntax error instead of stopping at the first. A statement with an
+    /// error is skipped up to the `;` or `}` that ends it, and parsing
+    /// carries on with the next, so the statements returned are the ones
+    /// that parsed cleanly.
+    pub fn parse_recovering(&mut self) -> (Vec<Stmt>, Vec<ParseError>) {
+        self.recovering = true;
+        let mut stmts = Vec::new();
+        let result = self.parse_program(&mut stmts);
+        self.recovering = false;
+        let mut errors = mem::take(&mut self.errors);
+        errors.extend(result.err());
+        (stmts, errors)
+    }
+
+    fn parse_program(&mut self, stmts: &mut Vec<Stmt>) -> Result<(), ParseError> {
+        self.skip_newlines();
+        while !self.at_end() {
+            match self.parse_stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(err) => self.recover(err)?,
+            }
+            self.skip_newlines();
+        }
+        Ok(())
+    }
+
+    /// Returns `err` unless recovering, in which case it is recorded and the
+    /// rest of the statement it was found in is skipped. Running out of
+    /// input cannot be recovered from.
+    fn recover(&mut self, err: ParseError) -> Result<(), ParseError> {
+        if !self.recovering || matches!(err, ParseError::UnexpectedEof { .. }) {
+            return Err(err);
+        }
+        let ended = match err {
+            ParseError::UnexpectedToken { ref found, .. }
+            | ParseError::ExpectedToken { ref found, .. } => {
+                *found == Token::Semicolon || *found == Token::Newline
+            }
+            _ => false,
+        };
+        self.errors.push(err);
+        if ended {
+            // The error was at the end of the statement, which has already
+            // been read.
+            return Ok(());
+        }
+
+        // Skip to the end of the statement, which is a `;` or line break
+        // outside of any braces, or the `}` closing a block that it began.
+        // A `}` closing a block around the statement is left for that block.
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                None | Some(&Token::Eof) => return Ok(()),
+                Some(&Token::BraceRight) if depth == 0 => return Ok(()),
+                _ => {}
+            }
+            match self.tokens.next() {
+                Some(Token::BraceLeft) => depth += 1,
+                Some(Token::BraceRight) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Some(Token::Semicolon) | Some(Token::Newline) if depth == 0 => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Skips the line breaks between statements.
+    fn skip_newlines(&mut self) {
+        while self.eat(&Token::Newline) {}
+    }
+
+    /// Consumes the end of a statement.
+    fn end_stmt(&mut self) -> Result<(), ParseError> {
+        if self.newline_terminated
+            && (self.eat(&Token::Newline)
+                || self.at_end()
+                || self.peek() == Some(&Token::BraceRight))
+        {
+            return Ok(());
+        }
+        self.expect(Token::Semicolon)
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.tokens.at_end()
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek()
+    }
+
+    fn next(&mut self) -> Result<Spanned<Token>, ParseError> {
+        if self.at_end() {
+            return Err(self.tokens.unexpected_eof());
+        }
+        self.tokens
+            .next_spanned()
+            .ok_or_else(|| self.tokens.unexpected_eof())
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        self.tokens.eat(token)
+    }
+
+    /// Returns the span of the next token without consuming it.
+    fn peek_span(&mut self) -> Result<Span, ParseError> {
+        match self.tokens.peek_spanned() {
+            Some(token) => Ok(token.span),
+            None => Err(self.tokens.unexpected_eof()),
+        }
+    }
+
+    /// Returns the span from `start` to the end of the last token consumed.
+    fn span_from(&self, start: Span) -> Span {
+        start.merge(self.tokens.last_span())
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        self.tokens.expect(token).map_err(|err| match err {
+            ParseError::ExpectedToken {
+                found: Token::EqualSign,
+                span,
+                ..
+            } => ParseError::SingleEquals { span },
+            err => err,
+        })
+    }
+
+    fn expect_name(&mut self) -> Result<String, ParseError> {
+        let next = self.next()?;
+        match next.node {
+            Token::Name(name) => Ok(name),
+            _ => Err(unexpected(next)),
+        }
+    }
+
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        let next = self.next()?;
+        match next.node {
+            Token::Name(ref name) => Type::from_name(name),
+            // `array` is lexed as a keyword.
+            Token::Array => Some(Type::Array),
+            _ => None,
+        }
+        .ok_or_else(|| unexpected(next))
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let first = self.next()?;
+        let stmt = match first.node {
+            Token::Let => {
+                let name = self.expect_name()?;
+                let ty = if self.eat(&Token::Colon) {
+                    Some(self.parse_type()?)
+                } else {
+                    None
+                };
+                self.expect(Token::Assign)?;
+                let value = self.parse_expr()?;
+                Stmt::Let { name, ty, value }
+            }
+            Token::Array => {
+                self.expect(Token::BracketLeft)?;
+                let size = self.parse_expr()?;
+                self.expect(Token::BracketRight)?;
+                let element = self.parse_type()?;
+                let name = self.expect_name()?;
+                Stmt::ArrayDecl {
+                    name,
+                    size,
+                    element,
+                }
+            }
+            Token::Name(name) => {
+                if self.eat(&Token::ParenLeft) {
+                    let args = self.parse_list(Token::ParenRight, Parser::parse_expr)?;
+                    let span = self.span_from(first.span);
+                    Stmt::Expr(Expr::Call { name, args, span })
+                } else {
+                    self.parse_assign(name, first.span)?
+                }
+            }
+            Token::Func => {
+                let name = self.expect_name()?;
+                self.expect(Token::ParenLeft)?;
+                let params = self.parse_list(Token::ParenRight, Parser::expect_name)?;
+                // Loops and block expressions around a definition do not
+                // enclose its body.
+                let (loop_depth, in_block_expr) = (self.loop_depth, self.in_block_expr);
+                self.loop_depth = 0;
+                self.in_block_expr = false;
+                let body = self.parse_block();
+                self.loop_depth = loop_depth;
+                self.in_block_expr = in_block_expr;
+                let body = body?;
+                return Ok(Stmt::FuncDef { name, params, body });
+            }
+            Token::Print => {
+                let mut args = vec![self.parse_print_arg()?];
+                while self.eat(&Token::Comma) {
+                    args.push(self.parse_print_arg()?);
+                }
+                Stmt::Print(args)
+            }
+            Token::While => {
+                let cond = self.parse_expr()?;
+                let body = self.parse_loop_body()?;
+                return Ok(Stmt::While { cond, body });
+            }
+            Token::If => {
+                let cond = self.parse_expr()?;
+                let then_branch = self.parse_block()?;
+                let else_branch = if self.eat(&Token::Else) {
+                    Some(self.parse_block()?)
+                } else {
+                    None
+                };
+                return Ok(Stmt::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                });
+            }
+            Token::For => {
+                let name = self.expect_name()?;
+                self.expect(Token::In)?;
+                let iterable = self.parse_expr()?;
+                let body = self.parse_loop_body()?;
+                return Ok(Stmt::ForEach {
+                    name,
+                    iterable,
+                    body,
+                });
+            }
+            Token::Break | Token::Continue if self.loop_depth == 0 => {
+                return Err(ParseError::OutsideLoop {
+                    found: first.node,
+                    span: first.span,
+                })
+            }
+            Token::Break => Stmt::Break,
+            Token::Continue => Stmt::Continue,
+            // A block expression must produce a value, so it cannot return
+            // early.
+            Token::Return if self.in_block_expr => return Err(unexpected(first)),
+            Token::Return => {
+                let bare = self.at_end()
+                    || matches!(
+                        self.peek(),
+                        Some(&Token::Semicolon) | Some(&Token::BraceRight) | Some(&Token::Newline)
+                    );
+                let value = if bare { None } else { Some(self.parse_expr()?) };
+                Stmt::Return(value)
+            }
+            Token::Assert => {
+                let cond = self.parse_expr()?;
+                let message = if self.eat(&Token::Comma) {
+                    Some(self.parse_expr()?)
+                } else {
+                    None
+                };
+                Stmt::Assert {
+                    cond,
+                    message,
+                    span: first.span,
+                }
+            }
+            _ => return Err(unexpected(first)),
+        };
+        self.end_stmt()?;
+        Ok(stmt)
+    }
+
+    /// Parses an argument to `print`, which may be followed by `: format`.
+    fn parse_print_arg(&mut self) -> Result<Expr, ParseError> {
+        let value = self.parse_expr()?;
+        if !self.eat(&Token::Colon) {
+            return Ok(value);
+        }
+        let next = self.next()?;
+        let format = match next.node {
+            Token::Name(ref name) => Format::from_name(name),
+            _ => None,
+        }
+        .ok_or_else(|| unexpected(next))?;
+        Ok(Expr::Formatted {
+            value: Box::new(value),
+            format,
+        })
+    }
+
+    /// Parses the rest of an assignment to `name`, whose name has already
+    /// been consumed from `start`.
+    fn parse_assign(&mut self, name: String, start: Span) -> Result<Stmt, ParseError> {
+        if let Some(op) = self.peek().and_then(compound_assign_op) {
+            // `x += 1` is shorthand for `x := x + 1`.
+            self.tokens.next();
+            let rhs = self.parse_expr()?;
+            let value = Expr::Binary {
+                op,
+                lhs: Box::new(Expr::Name(name.clone())),
+                rhs: Box::new(rhs),
+                span: self.span_from(start),
+            };
+            return Ok(Stmt::Assign { name, value });
+        }
+        self.expect(Token::Assign)?;
+        let value = self.parse_expr()?;
+        Ok(Stmt::Assign { name, value })
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect(Token::BraceLeft)?;
+        let mut stmts = Vec::new();
+        self.skip_newlines();
+        while !self.eat(&Token::BraceRight) {
+            match self.parse_stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(err) => self.recover(err)?,
+            }
+            self.skip_newlines();
+        }
+        Ok(stmts)
+    }
+
+    fn parse_loop_body(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.loop_depth += 1;
+        let body = self.parse_block();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.parse_expr_bp(0)
+    }
+
+    /// Parses an expression made of operators that bind at least as tightly
+    /// as `min_bp`, as given by `prefix_binding_power` and
+    /// `infix_binding_power`. Comparisons do not chain, since `a < b < c`
+    /// would compare a boolean with `c`.
+    fn parse_expr_bp(&mut self, min_bp: u8) -> Result<Expr, ParseError> {
+        let start = self.peek_span()?;
+        let lhs = self.parse_prefix(min_bp)?;
+        self.parse_infix(lhs, start, min_bp)
+    }
+
+    /// Parses an operand, with any prefix operators that bind at least as
+    /// tightly as `min_bp`. The lexer only produces non-negative integers, so
+    /// a minus directly before an integer literal is folded into a negative
+    /// literal here; whether a `-` is negation or subtraction is decided by
+    /// where it appears.
+    fn parse_prefix(&mut self, min_bp: u8) -> Result<Expr, ParseError> {
+        let op = match self.peek().and_then(prefix_op) {
+            Some(op) => op,
+            None => return self.parse_postfix(),
+        };
+        let next = self.next()?;
+        let bp = prefix_binding_power(op);
+        if bp < min_bp {
+            return Err(unexpected(next));
+        }
+        let operand = match self.peek() {
+            Some(&Token::Integer(n)) if op == UnaryOp::Neg => {
+                let literal = self.next()?;
+                // `**` binds tighter than negation, so `-2 ** 2` is `-(2 ** 2)`.
+                if self.peek() != Some(&Token::DoubleAsterisk) {
+                    return Ok(Expr::Integer(-n));
+                }
+                self.parse_infix(Expr::Integer(n), literal.span, bp)?
+            }
+            _ => self.parse_expr_bp(bp)?,
+        };
+        Ok(Expr::Unary {
+            op,
+            operand: Box::new(operand),
+            span: self.span_from(next.span),
+        })
+    }
+
+    /// Parses the binary operators and right operands that follow `lhs`, for
+    /// as long as the operators bind at least as tightly as `min_bp`. `start`
+    /// is the span of the first token of `lhs`.
+    fn parse_infix(&mut self, mut lhs: Expr, start: Span, min_bp: u8) -> Result<Expr, ParseError> {
+        let mut compared = false;
+        if let Some(token) = self.tokens.peek_spanned() {
+            if token.node == Token::EqualSign {
+                return Err(ParseError::SingleEquals { span: token.span });
+            }
+        }
+        while let Some(op) = self.peek().and_then(binary_op) {
+            let (left_bp, right_bp) = infix_binding_power(op);
+            if left_bp < min_bp {
+                break;
+            }
+            let next = self.next()?;
+            if op.is_comparison() {
+                if compared {
+                    let span = start.merge(next.span);
+                    return Err(ParseError::ChainedComparison { span });
+                }
+                compared = true;
+            } else {
+                compared = false;
+            }
+            let rhs = self.parse_expr_bp(right_bp)?;
+            lhs = Expr::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                span: self.span_from(start),
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// Parses the rest of a block expression whose `{` has already been
+    /// consumed. Its statements cannot leave an enclosing loop or function,
+    /// since the block must produce a value.
+    fn parse_block_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.tokens.last_span();
+        let (loop_depth, in_block_expr) = (self.loop_depth, self.in_block_expr);
+        self.loop_depth = 0;
+        self.in_block_expr = true;
+        let block = self.parse_block_expr_items(start);
+        self.loop_depth = loop_depth;
+        self.in_block_expr = in_block_expr;
+        block
+    }
+
+    fn parse_block_expr_items(&mut self, start: Span) -> Result<Expr, ParseError> {
+        let mut stmts = Vec::new();
+        loop {
+            self.skip_newlines();
+            let if_value = self.peek() == Some(&Token::If) && self.if_is_value();
+            if !if_value && self.peek().is_some_and(starts_keyword_stmt) {
+                stmts.push(self.parse_stmt()?);
+                continue;
+            }
+
+            // Anything else is an assignment, a call statement or the value
+            // of the block, which can only be told apart once the
+            // expression at their start has been read.
+            let value_start = self.peek_span()?;
+            let value = self.parse_expr()?;
+            let value = match value {
+                Expr::Name(name)
+                    if self.peek() == Some(&Token::Assign)
+                        || self.peek().and_then(compound_assign_op).is_some() =>
+                {
+                    stmts.push(self.parse_assign(name, value_start)?);
+                    self.end_stmt()?;
+                    continue;
+                }
+                value => value,
+            };
+            let newline = self.eat(&Token::Newline);
+            self.skip_newlines();
+            if self.peek() == Some(&Token::BraceRight) || !matches!(value, Expr::Call { .. }) {
+                self.expect(Token::BraceRight)?;
+                return Ok(Expr::Block {
+                    stmts,
+                    value: Box::new(value),
+                    span: self.span_from(start),
+                });
+            }
+            if !newline {
+                self.end_stmt()?;
+            }
+            stmts.push(Stmt::Expr(value));
+        }
+    }
+
+    /// Returns whether the `if` that comes next, as an item of a block
+    /// expression, is the value of the block rather than an `if` statement:
+    /// whether every `if` in its chain has an `else` and the chain is
+    /// followed by the `}` of the block. Only the conditions are parsed to
+    /// find out; the branches are skipped by matching braces.
+    fn if_is_value(&self) -> bool {
+        let mut ahead = Parser {
+            tokens: self.tokens.clone(),
+            newline_terminated: self.newline_terminated,
+            loop_depth: self.loop_depth,
+            in_block_expr: self.in_block_expr,
+            recovering: false,
+            errors: Vec::new(),
+        };
+        if !ahead.eat(&Token::If) {
+            return false;
+        }
+        loop {
+            if ahead.parse_expr().is_err() || !ahead.skip_braces() || !ahead.eat(&Token::Else) {
+                return false;
+            }
+            if !ahead.eat(&Token::If) {
+                break;
+            }
+        }
+        if !ahead.skip_braces() {
+            return false;
+        }
+        ahead.skip_newlines();
+        ahead.peek() == Some(&Token::BraceRight)
+    }
+
+    /// Consumes a `{` and everything up to and including the `}` matching
+    /// it. Returns false if there is no `{` or it is never closed.
+    fn skip_braces(&mut self) -> bool {
+        if !self.eat(&Token::BraceLeft) {
+            return false;
+        }
+        let mut depth = 1;
+        while depth > 0 {
+            match self.tokens.next() {
+                Some(Token::BraceLeft) => depth += 1,
+                Some(Token::BraceRight) => depth -= 1,
+                Some(_) => {}
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Parses the rest of an `if` expression whose `if` has already been
+    /// consumed. Unlike an `if` statement, it must have an `else` branch.
+    fn parse_if_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.tokens.last_span();
+        let cond = self.parse_expr()?;
+        self.expect(Token::BraceLeft)?;
+        let then_branch = self.parse_block_expr()?;
+        self.expect(Token::Else)?;
+        let else_branch = if self.eat(&Token::If) {
+            self.parse_if_expr()?
+        } else {
+            self.expect(Token::BraceLeft)?;
+            self.parse_block_expr()?
+        };
+        Ok(Expr::If {
+            cond: Box::new(cond),
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+            span: self.span_from(start),
+        })
+    }
+
+    /// Parses a factor followed by any number of `[index]` suffixes.
+    fn parse_postfix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.peek_span()?;
+        let mut expr = self.parse_factor()?;
+        while self.eat(&Token::BracketLeft) {
+            let index = self.parse_expr()?;
+            self.expect(Token::BracketRight)?;
+            expr = Expr::Index {
+                target: Box::new(expr),
+                index: Box::new(index),
+                span: self.span_from(start),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn parse_factor(&mut self) -> Result<Expr, ParseError> {
+        let next = self.next()?;
+        match next.node {
+            Token::Boolean(b) => Ok(Expr::Boolean(b)),
+            Token::Integer(n) => Ok(Expr::Integer(n)),
+            Token::StringLiteral(s) => Ok(Expr::String(s)),
+            Token::Name(name) => {
+                if self.eat(&Token::ParenLeft) {
+                    let args = self.parse_list(Token::ParenRight, Parser::parse_expr)?;
+                    let span = self.span_from(next.span);
+                    Ok(Expr::Call { name, args, span })
+                } else {
+                    Ok(Expr::Name(name))
+                }
+            }
+            Token::ParenLeft => {
+                let expr = self.parse_expr()?;
+                self.expect(Token::ParenRight)?;
+                Ok(expr)
+            }
+            Token::BracketLeft => {
+                let elements = self.parse_list(Token::BracketRight, Parser::parse_expr)?;
+                Ok(Expr::ArrayLiteral(elements))
+            }
+            Token::BraceLeft => self.parse_block_expr(),
+            Token::If => self.parse_if_expr(),
+            _ => Err(unexpected(next)),
+        }
+    }
+
+    /// Parses a possibly empty list of `item`s separated by commas, up to and
+    /// including the `close` token.
+    fn parse_list<T>(
+        &mut self,
+        close: Token,
+        item: fn(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        let mut items = Vec::new();
+        if self.eat(&close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(close)?;
+        Ok(items)
+    }
+}
+
+/// Returns whether `token` begins a statement that cannot also be read as an
+/// expression.
+fn starts_keyword_stmt(token: &Token) -> bool {
+    matches!(
+        *token,
+        Token::Let
+            | Token::Array
+            | Token::Func
+            | Token::Print
+            | Token::While
+            | Token::If
+            | Token::For
+            | Token::Break
+            | Token::Continue
+            | Token::Return
+            | Token::Assert
+    )
+}
+
+fn compound_assign_op(token: &Token) -> Option<BinaryOp> {
+    match *token {
+        Token::PlusAssign => Some(BinaryOp::Add),
+        Token::MinusAssign => Some(BinaryOp::Sub),
+        Token::AsteriskAssign => Some(BinaryOp::Mul),
+        Token::SlashAssign => Some(BinaryOp::Div),
+        _ => None,
+    }
+}
+
+fn prefix_op(token: &Token) -> Option<UnaryOp> {
+    match *token {
+        Token::Minus => Some(UnaryOp::Neg),
+        Token::Not => Some(UnaryOp::Not),
+        _ => None,
+    }
+}
+
+pub(crate) fn binary_op(token: &Token) -> Option<BinaryOp> {
+    match *token {
+        Token::Plus => Some(BinaryOp::Add),
+        Token::Minus => Some(BinaryOp::Sub),
+        Token::Asterisk => Some(BinaryOp::Mul),
+        Token::Slash => Some(BinaryOp::Div),
+        Token::Percent => Some(BinaryOp::Rem),
+        Token::DoubleAsterisk => Some(BinaryOp::Pow),
+
+        Token::Eq => Some(BinaryOp::Eq),
+        Token::Ne => Some(BinaryOp::Ne),
+        Token::Lt => Some(BinaryOp::Lt),
+        Token::Le => Some(BinaryOp::Le),
+        Token::Gt => Some(BinaryOp::Gt),
+        Token::Ge => Some(BinaryOp::Ge),
+
+        Token::And => Some(BinaryOp::And),
+        Token::Or => Some(BinaryOp::Or),
+        _ => None,
+    }
+}
+
+/// Returns how tightly a prefix operator binds its operand. An operator may
+/// only appear where the expression around it binds no more tightly, so
+/// `not` cannot begin the operand of a comparison.
+fn prefix_binding_power(op: UnaryOp) -> u8 {
+    match op {
+        UnaryOp::Not => 5,
+        UnaryOp::Neg => 13,
+    }
+}
+
+/// Returns how tightly a binary operator binds its left and right operands.
+/// An operator binding its right operand more tightly groups from the left,
+/// and one binding its left operand more tightly groups from the right.
+pub(crate) fn infix_binding_power(op: BinaryOp) -> (u8, u8) {
+    match op {
+        BinaryOp::Or => (1, 2),
+        BinaryOp::And => (3, 4),
+        BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+            (7, 8)
+        }
+        BinaryOp::Add | BinaryOp::Sub => (9, 10),
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => (11, 12),
+        // The exponent may be negated, as in `2 ** -1`.
+        BinaryOp::Pow => (15, 13),
+    }
+}
+
+fn unexpected(token: Spanned<Token>) -> ParseError {
+    ParseError::UnexpectedToken {
+        found: token.node,
+        span: token.span,
+    }
+}
+
+/// Parses a complete program from source text.
+pub fn parse(input: &str) -> Result<Vec<Stmt>, ParseError> {
+    Parser::new(Tokenizer::new(input)).parse()
+}
+
+/// Parses a complete program from source text, returning the statements
+/// that parsed along with every syntax error found, as an editor would list
+/// them. See `Parser::parse_recovering`.
+pub fn collect_errors(input: &str) -> (Vec<Stmt>, Vec<ParseError>) {
+    Parser::new(Tokenizer::new(input)).parse_recovering()
+}
+
+/// A pass over a syntax tree. Each method visits the children of its node by
+/// default, so a pass only needs to override the methods for the nodes it
+/// cares about, calling `walk_stmt` or `walk_expr` to keep descending.
+pub trait Visitor {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+/// Visits the statements and expressions directly inside `stmt`.
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match *stmt {
+        Stmt::Let { ref value, .. } | Stmt::Assign { ref value, .. } => visitor.visit_expr(value),
+        Stmt::ArrayDecl { ref size, .. } => visitor.visit_expr(size),
+        Stmt::Print(ref args) => {
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Stmt::While { ref cond, ref body } => {
+            visitor.visit_expr(cond);
+            walk_block(visitor, body);
+        }
+        Stmt::If {
+            ref cond,
+            ref then_branch,
+            ref else_branch,
+        } => {
+            visitor.visit_expr(cond);
+            walk_block(visitor, then_branch);
+            if let Some(ref else_branch) = *else_branch {
+                walk_block(visitor, else_branch);
+            }
+        }
+        Stmt::FuncDef { ref body, .. } => walk_block(visitor, body),
+        Stmt::Expr(ref expr) => visitor.visit_expr(expr),
+        Stmt::ForEach {
+            ref iterable,
+            ref body,
+            ..
+        } => {
+            visitor.visit_expr(iterable);
+            walk_block(visitor, body);
+        }
+        Stmt::Return(ref value) => {
+            if let Some(ref value) = *value {
+                visitor.visit_expr(value);
+            }
+        }
+        Stmt::Assert {
+            ref cond,
+            ref message,
+            ..
+        } => {
+            visitor.visit_expr(cond);
+            if let Some(ref message) = *message {
+                visitor.visit_expr(message);
+            }
+        }
+        Stmt::Break | Stmt::Continue => {}
+    }
+}
+
+/// Visits the expressions directly inside `expr`.
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match *expr {
+        Expr::Boolean(_) | Expr::Integer(_) | Expr::String(_) | Expr::Name(_) => {}
+        Expr::ArrayLiteral(ref elements) => {
+            for element in elements {
+                visitor.visit_expr(element);
+            }
+        }
+        Expr::Index {
+            ref target,
+            ref index,
+            ..
+        } => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(index);
+        }
+        Expr::Call { ref args, .. } => {
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::Unary { ref operand, .. }
+        | Expr::Formatted {
+            value: ref operand, ..
+        } => visitor.visit_expr(operand),
+        Expr::Binary {
+            ref lhs, ref rhs, ..
+        } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        Expr::Block {
+            ref stmts,
+            ref value,
+            ..
+        } => {
+            walk_block(visitor, stmts);
+            visitor.visit_expr(value);
+        }
+        Expr::If {
+            ref cond,
+            ref then_branch,
+            ref else_branch,
+            ..
+        } => {
+            visitor.visit_expr(cond);
+            visitor.visit_expr(then_branch);
+            visitor.visit_expr(else_branch);
+        }
+    }
+}
+
+fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, stmts: &[Stmt]) {
+    for stmt in stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+/// Collects the names of all variables read in the statements it visits.
+#[derive(Debug, Default)]
+pub struct NameCollector {
+    pub names: BTreeSet<String>,
+}
+
+impl Visitor for NameCollector {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Name(ref name) = *expr {
+            self.names.insert(name.clone());
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Replaces each operation on literals in `stmts` with the literal it
+/// evaluates to, working up from the innermost. Operations that would fail
+/// at runtime, such as division by zero, are left for the interpreter to
+/// report.
+pub fn fold_constants(stmts: &mut [Stmt]) {
+    walk_block_mut(stmts, &mut fold_expr);
+}
+
+/// Folds `expr`, whose operands have already been folded.
+fn fold_expr(expr: &mut Expr) {
+    let folded = match *expr {
+        Expr::Unary {
+            op, ref operand, ..
+        } => literal_value(operand).and_then(|operand| ops::unary(op, operand).ok()),
+        Expr::Binary {
+            op,
+            ref lhs,
+            ref rhs,
+            ..
+        } => match (literal_value(lhs), literal_value(rhs)) {
+            (Some(lhs), Some(rhs)) => ops::binary(op, lhs, rhs).ok(),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(value) = folded {
+        *expr = literal_expr(value);
+    }
+}
+
+/// Calls `f` on every expression in `stmts`, each after the expressions
+/// inside it.
+fn walk_block_mut(stmts: &mut [Stmt], f: &mut dyn FnMut(&mut Expr)) {
+    for stmt in stmts {
+        walk_stmt_mut(stmt, f);
+    }
+}
+
+fn walk_stmt_mut(stmt: &mut Stmt, f: &mut dyn FnMut(&mut Expr)) {
+    match *stmt {
+        Stmt::Let { ref mut value, .. } | Stmt::Assign { ref mut value, .. } => {
+            walk_expr_mut(value, f)
+        }
+        Stmt::ArrayDecl { ref mut size, .. } => walk_expr_mut(size, f),
+        Stmt::Print(ref mut args) => {
+            for arg in args {
+                walk_expr_mut(arg, f);
+            }
+        }
+        Stmt::While {
+            ref mut cond,
+            ref mut body,
+        } => {
+            walk_expr_mut(cond, f);
+            walk_block_mut(body, f);
+        }
+        Stmt::If {
+            ref mut cond,
+            ref mut then_branch,
+            ref mut else_branch,
+        } => {
+            walk_expr_mut(cond, f);
+            walk_block_mut(then_branch, f);
+            if let Some(ref mut else_branch) = *else_branch {
+                walk_block_mut(else_branch, f);
+            }
+        }
+        Stmt::FuncDef { ref mut body, .. } => walk_block_mut(body, f),
+        Stmt::Expr(ref mut expr) => walk_expr_mut(expr, f),
+        Stmt::ForEach {
+            ref mut iterable,
+            ref mut body,
+            ..
+        } => {
+            walk_expr_mut(iterable, f);
+            walk_block_mut(body, f);
+        }
+        Stmt::Return(ref mut value) => {
+            if let Some(ref mut value) = *value {
+                walk_expr_mut(value, f);
+            }
+        }
+        Stmt::Assert {
+            ref mut cond,
+            ref mut message,
+            ..
+        } => {
+            walk_expr_mut(cond, f);
+            if let Some(ref mut message) = *message {
+                walk_expr_mut(message, f);
+            }
+        }
+        Stmt::Break | Stmt::Continue => {}
+    }
+}
+
+fn walk_expr_mut(expr: &mut Expr, f: &mut dyn FnMut(&mut Expr)) {
+    match *expr {
+        Expr::Boolean(_) | Expr::Integer(_) | Expr::String(_) | Expr::Name(_) => {}
+        Expr::ArrayLiteral(ref mut elements)
+        | Expr::Call {
+            args: ref mut elements,
+            ..
+        } => {
+            for element in elements {
+                walk_expr_mut(element, f);
+            }
+        }
+        Expr::Index {
+            ref mut target,
+            ref mut index,
+            ..
+        } => {
+            walk_expr_mut(target, f);
+            walk_expr_mut(index, f);
+        }
+        Expr::Unary {
+            ref mut operand, ..
+        }
+        | Expr::Formatted {
+            value: ref mut operand,
+            ..
+        } => walk_expr_mut(operand, f),
+        Expr::Binary {
+            ref mut lhs,
+            ref mut rhs,
+            ..
+        } => {
+            walk_expr_mut(lhs, f);
+            walk_expr_mut(rhs, f);
+        }
+        Expr::Block {
+            ref mut stmts,
+            ref mut value,
+            ..
+        } => {
+            walk_block_mut(stmts, f);
+            walk_expr_mut(value, f);
+        }
+        Expr::If {
+            ref mut cond,
+            ref mut then_branch,
+            ref mut else_branch,
+            ..
+        } => {
+            walk_expr_mut(cond, f);
+            walk_expr_mut(then_branch, f);
+            walk_expr_mut(else_branch, f);
+        }
+    }
+    f(expr);
+}
+
+/// Returns the value of `expr` if it is a literal.
+fn literal_value(expr: &Expr) -> Option<Scalar> {
+    match *expr {
+        Expr::Boolean(b) => Some(Scalar::Boolean(b)),
+        Expr::Integer(n) => Some(Scalar::Integer(n)),
+        Expr::String(ref s) => Some(Scalar::String(s.clone())),
+        _ => None,
+    }
+}
+
+/// Returns the literal that evaluates to `value`.
+fn literal_expr(value: Scalar) -> Expr {
+    match value {
+        Scalar::Boolean(b) => Expr::Boolean(b),
+        Scalar::Integer(n) => Expr::Integer(n),
+        Scalar::String(s) => Expr::String(s),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{walk_block_mut, BinaryOp, Expr, ParseError, Stmt};
+    use tokenizer::Span;
+
+    fn span(start: usize, end: usize, line: u32, col: u32) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            col,
+        }
+    }
+
+    /// Parses `source` with the span of every expression cleared, to compare
+    /// with a tree built by hand.
+    fn parse_unspanned(source: &str) -> Result<Vec<Stmt>, ParseError> {
+        let mut stmts = super::parse(source)?;
+        walk_block_mut(&mut stmts, &mut |expr| match *expr {
+            Expr::Index { ref mut span, .. }
+            | Expr::Call { ref mut span, .. }
+            | Expr::Unary { ref mut span, .. }
+            | Expr::Binary { ref mut span, .. }
+            | Expr::Block { ref mut span, .. }
+            | Expr::If { ref mut span, .. } => *span = Span::default(),
+            _ => {}
+        });
+        Ok(stmts)
+    }
+
+    fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span: Span::default(),
+        }
+    }
+
+    fn name(name: &str) -> Expr {
+        Expr::Name(name.to_string())
+    }
+
+    #[test]
+    fn token_stream_expect() {
+        use super::{ParseError, TokenStream};
+        use tokenizer::{Span, Token, Tokenizer};
+
+        let mut tokens = TokenStream::new(Tokenizer::new("x := 1"));
+        assert_eq!(tokens.expect(Token::Name("x".to_string())), Ok(()));
+        assert_eq!(tokens.peek(), Some(&Token::Assign));
+        assert!(!tokens.eat(&Token::Semicolon));
+        assert_eq!(
+            tokens.expect(Token::Semicolon),
+            Err(ParseError::ExpectedToken {
+                expected: Token::Semicolon,
+                found: Token::Assign,
+                span: Span {
+                    start: 2,
+                    end: 4,
+                    line: 1,
+                    col: 2,
+                },
+            })
+        );
+        assert!(tokens.eat(&Token::Integer(1)));
+        assert_eq!(
+            tokens.expect(Token::Semicolon),
+            Err(ParseError::UnexpectedEof {
+                span: span(6, 6, 1, 6),
+            })
+        );
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn let_precedence() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("let x := 1 + 2 * 3;"),
+            Ok(vec![Stmt::Let {
+                name: "x".to_string(),
+                ty: None,
+                value: binary(
+                    BinaryOp::Add,
+                    Expr::Integer(1),
+                    binary(BinaryOp::Mul, Expr::Integer(2), Expr::Integer(3)),
+                ),
+            }])
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        use super::{parse, Stmt};
+        use serde_json;
+
+        let stmts = parse(
+            "let x: int := -(1 + 2); func f(a) { while not a[0] { break; } } \
+             if x > 0 { f([x]); } else { print \"no\"; }",
+        )
+        .unwrap();
+        let json = serde_json::to_string(&stmts).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Stmt>>(&json).unwrap(), stmts);
+    }
+
+    #[test]
+    fn type_annotations() {
+        use super::{parse, ParseError, Stmt, Type};
+        use tokenizer::{Span, Token};
+
+        assert_eq!(
+            parse("let x: int := 5; let ys: array := [];"),
+            Ok(vec![
+                Stmt::Let {
+                    name: "x".to_string(),
+                    ty: Some(Type::Int),
+                    value: Expr::Integer(5),
+                },
+                Stmt::Let {
+                    name: "ys".to_string(),
+                    ty: Some(Type::Array),
+                    value: Expr::ArrayLiteral(vec![]),
+                },
+            ])
+        );
+        assert_eq!(
+            parse("let x: float := 5;"),
+            Err(ParseError::UnexpectedToken {
+                found: Token::Name("float".to_string()),
+                span: Span {
+                    start: 7,
+                    end: 12,
+                    line: 1,
+                    col: 7,
+                },
+            })
+        );
+        assert_eq!(
+            parse("let x: := 5;"),
+            Err(ParseError::UnexpectedToken {
+                found: Token::Assign,
+                span: Span {
+                    start: 7,
+                    end: 9,
+                    line: 1,
+                    col: 7,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn left_associative() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("print 8 - 4 - 2; print 8 / 4 * 2;"),
+            Ok(vec![
+                Stmt::Print(vec![binary(
+                    BinaryOp::Sub,
+                    binary(BinaryOp::Sub, Expr::Integer(8), Expr::Integer(4)),
+                    Expr::Integer(2),
+                )]),
+                Stmt::Print(vec![binary(
+                    BinaryOp::Mul,
+                    binary(BinaryOp::Div, Expr::Integer(8), Expr::Integer(4)),
+                    Expr::Integer(2),
+                )]),
+            ])
+        );
+    }
+
+    #[test]
+    fn remainder() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("print 7 % 3;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Rem,
+                Expr::Integer(7),
+                Expr::Integer(3),
+            )])])
+        );
+        assert_eq!(
+            parse_unspanned("print 1 + 7 % 3 * 2;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Add,
+                Expr::Integer(1),
+                binary(
+                    BinaryOp::Mul,
+                    binary(BinaryOp::Rem, Expr::Integer(7), Expr::Integer(3)),
+                    Expr::Integer(2),
+                ),
+            )])])
+        );
+    }
+
+    #[test]
+    fn comparison_below_arithmetic() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("print a + 1 <= b * 2;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Le,
+                binary(BinaryOp::Add, name("a"), Expr::Integer(1)),
+                binary(BinaryOp::Mul, name("b"), Expr::Integer(2)),
+            )])])
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("print (1 + 2) * 3;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Mul,
+                binary(BinaryOp::Add, Expr::Integer(1), Expr::Integer(2)),
+                Expr::Integer(3),
+            )])])
+        );
+    }
+
+    #[test]
+    fn unary_minus() {
+        use super::{Stmt, UnaryOp};
+
+        assert_eq!(
+            parse_unspanned("print -5;"),
+            Ok(vec![Stmt::Print(vec![Expr::Integer(-5)])])
+        );
+        assert_eq!(
+            parse_unspanned("print 3 - -2;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Sub,
+                Expr::Integer(3),
+                Expr::Integer(-2),
+            )])])
+        );
+        assert_eq!(
+            parse_unspanned("print -(a + b);"),
+            Ok(vec![Stmt::Print(vec![Expr::Unary {
+                op: UnaryOp::Neg,
+                operand: Box::new(binary(BinaryOp::Add, name("a"), name("b"))),
+                span: Span::default(),
+            }])])
+        );
+        assert_eq!(
+            parse_unspanned("print -a * 2;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Mul,
+                Expr::Unary {
+                    op: UnaryOp::Neg,
+                    operand: Box::new(name("a")),
+                    span: Span::default(),
+                },
+                Expr::Integer(2),
+            )])])
+        );
+    }
+
+    #[test]
+    fn logical_operators() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("print a or b and c < d or e;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Or,
+                binary(
+                    BinaryOp::Or,
+                    name("a"),
+                    binary(
+                        BinaryOp::And,
+                        name("b"),
+                        binary(BinaryOp::Lt, name("c"), name("d")),
+                    ),
+                ),
+                name("e"),
+            )])])
+        );
+    }
+
+    #[test]
+    fn binding_power() {
+        use super::{ParseError, Stmt};
+        use tokenizer::Token;
+
+        assert_eq!(
+            parse_unspanned("print 1 + 2 * 3 - 4, a and b or c;"),
+            Ok(vec![Stmt::Print(vec![
+                binary(
+                    BinaryOp::Sub,
+                    binary(
+                        BinaryOp::Add,
+                        Expr::Integer(1),
+                        binary(BinaryOp::Mul, Expr::Integer(2), Expr::Integer(3)),
+                    ),
+                    Expr::Integer(4),
+                ),
+                binary(
+                    BinaryOp::Or,
+                    binary(BinaryOp::And, name("a"), name("b")),
+                    name("c"),
+                ),
+            ])])
+        );
+
+        // A prefix operator cannot appear inside an operand that binds more
+        // tightly than it does.
+        for source in &["print 1 + not x;", "print a < not b;", "print -not c;"] {
+            assert!(matches!(
+                parse_unspanned(source),
+                Err(ParseError::UnexpectedToken {
+                    found: Token::Not,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn not() {
+        use super::{Stmt, UnaryOp};
+
+        assert_eq!(
+            parse_unspanned("print not a == b and not not c;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::And,
+                Expr::Unary {
+                    op: UnaryOp::Not,
+                    operand: Box::new(binary(BinaryOp::Eq, name("a"), name("b"))),
+                    span: Span::default(),
+                },
+                Expr::Unary {
+                    op: UnaryOp::Not,
+                    operand: Box::new(Expr::Unary {
+                        op: UnaryOp::Not,
+                        operand: Box::new(name("c")),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
+                },
+            )])])
+        );
+    }
+
+    #[test]
+    fn power() {
+        use super::{Stmt, UnaryOp};
+
+        assert_eq!(
+            parse_unspanned("print 2 * 3 ** 2 ** 4;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Mul,
+                Expr::Integer(2),
+                binary(
+                    BinaryOp::Pow,
+                    Expr::Integer(3),
+                    binary(BinaryOp::Pow, Expr::Integer(2), Expr::Integer(4)),
+                ),
+            )])])
+        );
+        assert_eq!(
+            parse_unspanned("print -2 ** -1;"),
+            Ok(vec![Stmt::Print(vec![Expr::Unary {
+                op: UnaryOp::Neg,
+                operand: Box::new(binary(BinaryOp::Pow, Expr::Integer(2), Expr::Integer(-1),)),
+                span: Span::default(),
+            }])])
+        );
+    }
+
+    #[test]
+    fn blocks() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("while x < 3 { x := x + 1; if x > 1 { print x; } }"),
+            Ok(vec![Stmt::While {
+                cond: binary(BinaryOp::Lt, name("x"), Expr::Integer(3)),
+                body: vec![
+                    Stmt::Assign {
+                        name: "x".to_string(),
+                        value: binary(BinaryOp::Add, name("x"), Expr::Integer(1)),
+                    },
+                    Stmt::If {
+                        cond: binary(BinaryOp::Gt, name("x"), Expr::Integer(1)),
+                        then_branch: vec![Stmt::Print(vec![name("x")])],
+                        else_branch: None,
+                    },
+                ],
+            }])
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("if x < 0 { print 1; } else { print 2; }"),
+            Ok(vec![Stmt::If {
+                cond: binary(BinaryOp::Lt, name("x"), Expr::Integer(0)),
+                then_branch: vec![Stmt::Print(vec![Expr::Integer(1)])],
+                else_branch: Some(vec![Stmt::Print(vec![Expr::Integer(2)])]),
+            }])
+        );
+    }
+
+    #[test]
+    fn functions() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("func add(a, b) { print a + b; } func hi() { } add(1, hi());"),
+            Ok(vec![
+                Stmt::FuncDef {
+                    name: "add".to_string(),
+                    params: vec!["a".to_string(), "b".to_string()],
+                    body: vec![Stmt::Print(vec![binary(
+                        BinaryOp::Add,
+                        name("a"),
+                        name("b")
+                    )])],
+                },
+                Stmt::FuncDef {
+                    name: "hi".to_string(),
+                    params: vec![],
+                    body: vec![],
+                },
+                Stmt::Expr(Expr::Call {
+                    name: "add".to_string(),
+                    args: vec![
+                        Expr::Integer(1),
+                        Expr::Call {
+                            name: "hi".to_string(),
+                            args: vec![],
+                            span: Span::default(),
+                        },
+                    ],
+                    span: Span::default(),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn compound_assignment() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("x *= y + 1;"),
+            Ok(vec![Stmt::Assign {
+                name: "x".to_string(),
+                value: binary(
+                    BinaryOp::Mul,
+                    name("x"),
+                    binary(BinaryOp::Add, name("y"), Expr::Integer(1)),
+                ),
+            }])
+        );
+    }
+
+    #[test]
+    fn print_list() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("print 1, a + 2, b;"),
+            Ok(vec![Stmt::Print(vec![
+                Expr::Integer(1),
+                binary(BinaryOp::Add, name("a"), Expr::Integer(2)),
+                name("b"),
+            ])])
+        );
+        assert!(parse_unspanned("print 1,;").is_err());
+        assert!(parse_unspanned("print;").is_err());
+    }
+
+    #[test]
+    fn print_formats() {
+        use super::{Format, ParseError, Stmt};
+        use tokenizer::Token;
+
+        assert_eq!(
+            parse_unspanned("print x + 1: hex, y, 5: bin;"),
+            Ok(vec![Stmt::Print(vec![
+                Expr::Formatted {
+                    value: Box::new(binary(BinaryOp::Add, name("x"), Expr::Integer(1))),
+                    format: Format::Hex,
+                },
+                name("y"),
+                Expr::Formatted {
+                    value: Box::new(Expr::Integer(5)),
+                    format: Format::Binary,
+                },
+            ])])
+        );
+        assert!(matches!(
+            parse_unspanned("print x: oct;"),
+            Err(ParseError::UnexpectedToken {
+                found: Token::Name(ref name),
+                ..
+            }) if name == "oct"
+        ));
+        assert!(matches!(
+            parse_unspanned("let y := x: hex;"),
+            Err(ParseError::ExpectedToken {
+                expected: Token::Semicolon,
+                found: Token::Colon,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn strings() {
+        use super::{parse, Stmt};
+
+        assert_eq!(
+            parse("print \"hi\";"),
+            Ok(vec![Stmt::Print(vec![Expr::String("hi".to_string())])])
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        use super::Stmt;
+
+        assert_eq!(
+            parse_unspanned("print [1, [], x][0] ** 2;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Pow,
+                Expr::Index {
+                    target: Box::new(Expr::ArrayLiteral(vec![
+                        Expr::Integer(1),
+                        Expr::ArrayLiteral(vec![]),
+                        name("x"),
+                    ])),
+                    index: Box::new(Expr::Integer(0)),
+                    span: Span::default(),
+                },
+                Expr::Integer(2),
+            )])])
+        );
+        assert_eq!(
+            parse_unspanned("print a[i][j + 1];"),
+            Ok(vec![Stmt::Print(vec![Expr::Index {
+                target: Box::new(Expr::Index {
+                    target: Box::new(name("a")),
+                    index: Box::new(name("i")),
+                    span: Span::default(),
+                }),
+                index: Box::new(binary(BinaryOp::Add, name("j"), Expr::Integer(1))),
+                span: Span::default(),
+            }])])
+        );
+    }
+
+    #[test]
+    fn array_declarations() {
+        use super::{ParseError, Stmt, Type};
+        use tokenizer::Token;
+
+        assert_eq!(
+            parse_unspanned("array[2 * n] int xs; array[1] array grid;"),
+            Ok(vec![
+                Stmt::ArrayDecl {
+                    name: "xs".to_string(),
+                    size: binary(BinaryOp::Mul, Expr::Integer(2), name("n")),
+                    element: Type::Int,
+                },
+                Stmt::ArrayDecl {
+                    name: "grid".to_string(),
+                    size: Expr::Integer(1),
+                    element: Type::Array,
+                },
+            ])
+        );
+        assert!(matches!(
+            parse_unspanned("array[3] int;"),
+            Err(ParseError::UnexpectedToken {
+                found: Token::Semicolon,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_unspanned("array int xs;"),
+            Err(ParseError::ExpectedToken {
+                expected: Token::BracketLeft,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn if_expressions() {
+        use super::{ParseError, Stmt};
+        use tokenizer::Token;
+
+        assert_eq!(
+            parse_unspanned("let y := if x > 0 { 1 } else { -1 };"),
+            Ok(vec![Stmt::Let {
+                name: "y".to_string(),
+                ty: None,
+                value: Expr::If {
+                    cond: Box::new(binary(BinaryOp::Gt, name("x"), Expr::Integer(0))),
+                    then_branch: Box::new(Expr::Block {
+                        stmts: vec![],
+                        value: Box::new(Expr::Integer(1)),
+                        span: Span::default(),
+                    }),
+                    else_branch: Box::new(Expr::Block {
+                        stmts: vec![],
+                        value: Box::new(Expr::Integer(-1)),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
+                },
+            }])
+        );
+        assert_eq!(
+            parse_unspanned("print { let a := 1; a += 1; f(a); a } * 2;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Mul,
+                Expr::Block {
+                    stmts: vec![
+                        Stmt::Let {
+                            name: "a".to_string(),
+                            ty: None,
+                            value: Expr::Integer(1),
+                        },
+                        Stmt::Assign {
+                            name: "a".to_string(),
+                            value: binary(BinaryOp::Add, name("a"), Expr::Integer(1)),
+                        },
+                        Stmt::Expr(Expr::Call {
+                            name: "f".to_string(),
+                            args: vec![name("a")],
+                            span: Span::default(),
+                        }),
+                    ],
+                    value: Box::new(name("a")),
+                    span: Span::default(),
+                },
+                Expr::Integer(2),
+            )])])
+        );
+        assert!(matches!(
+            parse_unspanned("let y := if x { 1 } else if y { 2 } else { f(3) };"),
+            Ok(ref stmts) if stmts.len() == 1
+        ));
+
+        // An `if` with an `else` that ends a block expression is its value.
+        let if_expr = |cond: &str, then_value: Expr, else_value: Expr| Expr::If {
+            cond: Box::new(name(cond)),
+            then_branch: Box::new(Expr::Block {
+                stmts: vec![],
+                value: Box::new(then_value),
+                span: Span::default(),
+            }),
+            else_branch: Box::new(Expr::Block {
+                stmts: vec![],
+                value: Box::new(else_value),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        assert_eq!(
+            parse_unspanned("let y := { if c { 1 } else { 2 } };"),
+            Ok(vec![Stmt::Let {
+                name: "y".to_string(),
+                ty: None,
+                value: Expr::Block {
+                    stmts: vec![],
+                    value: Box::new(if_expr("c", Expr::Integer(1), Expr::Integer(2))),
+                    span: Span::default(),
+                },
+            }])
+        );
+        assert_eq!(
+            parse_unspanned("let y := if a { if b { 1 } else { 2 } } else { 3 };"),
+            Ok(vec![Stmt::Let {
+                name: "y".to_string(),
+                ty: None,
+                value: Expr::If {
+                    cond: Box::new(name("a")),
+                    then_branch: Box::new(Expr::Block {
+                        stmts: vec![],
+                        value: Box::new(if_expr("b", Expr::Integer(1), Expr::Integer(2))),
+                        span: Span::default(),
+                    }),
+                    else_branch: Box::new(Expr::Block {
+                        stmts: vec![],
+                        value: Box::new(Expr::Integer(3)),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
+                },
+            }])
+        );
+        assert_eq!(
+            parse_unspanned("let y := {\n  let z := 1;\n  if c { z } else { 2 }\n};"),
+            Ok(vec![Stmt::Let {
+                name: "y".to_string(),
+                ty: None,
+                value: Expr::Block {
+                    stmts: vec![Stmt::Let {
+                        name: "z".to_string(),
+                        ty: None,
+                        value: Expr::Integer(1),
+                    }],
+                    value: Box::new(if_expr("c", name("z"), Expr::Integer(2))),
+                    span: Span::default(),
+                },
+            }])
+        );
+        // Otherwise it is a statement.
+        assert_eq!(
+            parse_unspanned("let y := { if c { f(); } else { g(); } if d { h(); } 1 };"),
+            Ok(vec![Stmt::Let {
+                name: "y".to_string(),
+                ty: None,
+                value: Expr::Block {
+                    stmts: vec![
+                        Stmt::If {
+                            cond: name("c"),
+                            then_branch: vec![Stmt::Expr(Expr::Call {
+                                name: "f".to_string(),
+                                args: vec![],
+                                span: Span::default(),
+                            })],
+                            else_branch: Some(vec![Stmt::Expr(Expr::Call {
+                                name: "g".to_string(),
+                                args: vec![],
+                                span: Span::default(),
+                            })]),
+                        },
+                        Stmt::If {
+                            cond: name("d"),
+                            then_branch: vec![Stmt::Expr(Expr::Call {
+                                name: "h".to_string(),
+                                args: vec![],
+                                span: Span::default(),
+                            })],
+                            else_branch: None,
+                        },
+                    ],
+                    value: Box::new(Expr::Integer(1)),
+                    span: Span::default(),
+                },
+            }])
+        );
+        assert!(matches!(
+            parse_unspanned("let y := { if c { f(); } };"),
+            Err(ParseError::UnexpectedToken {
+                found: Token::BraceRight,
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            parse_unspanned("let y := if x > 0 { 1 };"),
+            Err(ParseError::ExpectedToken {
+                expected: Token::Else,
+                found: Token::Semicolon,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_unspanned("let y := { x + 1; };"),
+            Err(ParseError::ExpectedToken {
+                expected: Token::BraceRight,
+                found: Token::Semicolon,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_unspanned("while true { print { break; 1 }; }"),
+            Err(ParseError::OutsideLoop {
+                found: Token::Break,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn for_each() {
+        use super::{parse, Stmt};
+
+        assert_eq!(
+            parse("for i in xs { print i; }"),
+            Ok(vec![Stmt::ForEach {
+                name: "i".to_string(),
+                iterable: name("xs"),
+                body: vec![Stmt::Print(vec![name("i")])],
+            }])
+        );
+    }
+
+    #[test]
+    fn eof_token() {
+        use super::{Parser, Stmt};
+        use tokenizer::Tokenizer;
+
+        assert_eq!(
+            Parser::new(Tokenizer::new("print 1;").with_eof()).parse(),
+            Ok(vec![Stmt::Print(vec![Expr::Integer(1)])])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        use super::{parse, ParseError, Parser};
+        use tokenizer::{Span, Token, Tokenizer};
+
+        assert_eq!(
+            parse("let x := 1\nprint x;"),
+            Err(ParseError::ExpectedToken {
+                expected: Token::Semicolon,
+                found: Token::Print,
+                span: Span {
+                    start: 11,
+                    end: 16,
+                    line: 2,
+                    col: 0,
+                },
+            })
+        );
+        // The end of input is placed just after the last token, before any
+        // trailing whitespace or `Token::Eof`.
+        assert_eq!(
+            parse("print 1 +\n  "),
+            Err(ParseError::UnexpectedEof {
+                span: span(9, 9, 1, 9),
+            })
+        );
+        assert_eq!(
+            Parser::new(Tokenizer::new("print 1 ").with_eof()).parse(),
+            Err(ParseError::UnexpectedEof {
+                span: span(7, 7, 1, 7),
+            })
+        );
+        assert_eq!(
+            parse("while true {\n  print 1; // done"),
+            Err(ParseError::UnexpectedEof {
+                span: span(23, 23, 2, 10),
+            })
+        );
+        assert_eq!(
+            parse("print"),
+            Err(ParseError::UnexpectedEof {
+                span: span(5, 5, 1, 5),
+            })
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        use super::parse;
+        use std::error::Error;
+
+        let err = parse("let x := 1\n  + 2 3;").unwrap_err();
+        assert_eq!(err.to_string(), "2:6: expected ';', found '3'");
+
+        let err = parse("print ;").unwrap_err();
+        assert_eq!(err.to_string(), "1:6: unexpected ';'");
+
+        let err = parse("print 1").unwrap_err();
+        assert_eq!(err.to_string(), "1:7: unexpected end of input");
+
+        let err: Box<dyn Error> = Box::new(parse("break;").unwrap_err());
+        assert_eq!(err.to_string(), "1:0: 'break' outside of a loop");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn newline_terminated() {
+        use super::{parse, ParseError, Parser, Stmt};
+        use tokenizer::{Token, Tokenizer};
+
+        let source = "print 1\nprint 2";
+        assert_eq!(
+            Parser::new(Tokenizer::new(source).with_newlines()).parse(),
+            Ok(vec![
+                Stmt::Print(vec![Expr::Integer(1)]),
+                Stmt::Print(vec![Expr::Integer(2)]),
+            ])
+        );
+        assert!(matches!(
+            parse(source),
+            Err(ParseError::ExpectedToken {
+                expected: Token::Semicolon,
+                found: Token::Print,
+                ..
+            })
+        ));
+
+        let source = "\nwhile x {\n\n  x := 1; print x\n}\nif x { print 2 }\n";
+        assert_eq!(
+            Parser::new(Tokenizer::new(source).with_newlines()).parse(),
+            Ok(vec![
+                Stmt::While {
+                    cond: name("x"),
+                    body: vec![
+                        Stmt::Assign {
+                            name: "x".to_string(),
+                            value: Expr::Integer(1),
+                        },
+                        Stmt::Print(vec![name("x")]),
+                    ],
+                },
+                Stmt::If {
+                    cond: name("x"),
+                    then_branch: vec![Stmt::Print(vec![Expr::Integer(2)])],
+                    else_branch: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn break_and_continue() {
+        use super::{parse, ParseError, Stmt};
+        use tokenizer::{Span, Token};
+
+        assert_eq!(
+            parse("while x { if y { break; } continue; }"),
+            Ok(vec![Stmt::While {
+                cond: name("x"),
+                body: vec![
+                    Stmt::If {
+                        cond: name("y"),
+                        then_branch: vec![Stmt::Break],
+                        else_branch: None,
+                    },
+                    Stmt::Continue,
+                ],
+            }])
+        );
+        assert_eq!(
+            parse("if x { break; }"),
+            Err(ParseError::OutsideLoop {
+                found: Token::Break,
+                span: Span {
+                    start: 7,
+                    end: 12,
+                    line: 1,
+                    col: 7,
+                },
+            })
+        );
+        assert!(matches!(
+            parse("for i in xs { func f() { continue; } }"),
+            Err(ParseError::OutsideLoop {
+                found: Token::Continue,
+                ..
+            })
+        ));
+        assert_eq!(
+            parse("while x { } break;").unwrap_err().to_string(),
+            "1:12: 'break' outside of a loop"
+        );
+    }
+
+    #[test]
+    fn single_equals() {
+        use super::{parse, ParseError};
+        use tokenizer::Span;
+
+        let err = parse("if a = b { }").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::SingleEquals {
+                span: Span {
+                    start: 5,
+                    end: 6,
+                    line: 1,
+                    col: 5,
+                },
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "1:5: unexpected '='; use '==' to compare or ':=' to assign"
+        );
+
+        for source in &["let x = 1;", "x = 1;", "print a + b = c;", "f(a = b);"] {
+            assert!(
+                matches!(parse(source), Err(ParseError::SingleEquals { .. })),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn collect_errors() {
+        use super::{collect_errors, ParseError, Stmt};
+        use tokenizer::Token;
+
+        let (stmts, errors) = collect_errors("let x := 1; let := 2; print x; print (; let y := x;");
+        assert_eq!(
+            stmts,
+            vec![
+                Stmt::Let {
+                    name: "x".to_string(),
+                    ty: None,
+                    value: Expr::Integer(1),
+                },
+                Stmt::Print(vec![name("x")]),
+                Stmt::Let {
+                    name: "y".to_string(),
+                    ty: None,
+                    value: name("x"),
+                },
+            ]
+        );
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0],
+            ParseError::UnexpectedToken {
+                found: Token::Assign,
+                ..
+            }
+        ));
+        assert!(matches!(
+            errors[1],
+            ParseError::UnexpectedToken {
+                found: Token::Semicolon,
+                ..
+            }
+        ));
+
+        // Errors inside a block leave the rest of the block, and a broken
+        // block statement is skipped as a whole.
+        let (stmts, errors) =
+            collect_errors("while x { print ); print 1; } func (x) { x := 1; } print 2; x := ");
+        assert_eq!(
+            stmts,
+            vec![
+                Stmt::While {
+                    cond: name("x"),
+                    body: vec![Stmt::Print(vec![Expr::Integer(1)])],
+                },
+                Stmt::Print(vec![Expr::Integer(2)]),
+            ]
+        );
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(errors[2], ParseError::UnexpectedEof { .. }));
+
+        assert_eq!(
+            collect_errors("print 1;"),
+            (vec![Stmt::Print(vec![Expr::Integer(1)])], vec![])
+        );
+    }
+
+    #[test]
+    fn return_statement() {
+        use super::{ParseError, Parser, Stmt};
+        use tokenizer::{Token, Tokenizer};
+
+        assert_eq!(
+            parse_unspanned("func f(x) { if x { return; } return x + 1; }"),
+            Ok(vec![Stmt::FuncDef {
+                name: "f".to_string(),
+                params: vec!["x".to_string()],
+                body: vec![
+                    Stmt::If {
+                        cond: name("x"),
+                        then_branch: vec![Stmt::Return(None)],
+                        else_branch: None,
+                    },
+                    Stmt::Return(Some(binary(BinaryOp::Add, name("x"), Expr::Integer(1)))),
+                ],
+            }])
+        );
+        let mut parser =
+            Parser::new(Tokenizer::new("return\nreturn 1\nwhile x { return }").with_newlines());
+        assert_eq!(
+            parser.parse(),
+            Ok(vec![
+                Stmt::Return(None),
+                Stmt::Return(Some(Expr::Integer(1))),
+                Stmt::While {
+                    cond: name("x"),
+                    body: vec![Stmt::Return(None)],
+                },
+            ])
+        );
+        assert!(matches!(
+            parse_unspanned("func f() { let x := { return 1; 2 }; }"),
+            Err(ParseError::UnexpectedToken {
+                found: Token::Return,
+                ..
+            })
+        ));
+        assert!(parse_unspanned("let x := { func f() { return 1; } f() };").is_ok());
+    }
+
+    #[test]
+    fn assert_statement() {
+        use super::Stmt;
+        use tokenizer::Span;
+
+        assert_eq!(
+            parse_unspanned("assert x;\n  assert x == 1, \"x is \" + x;"),
+            Ok(vec![
+                Stmt::Assert {
+                    cond: name("x"),
+                    message: None,
+                    span: Span {
+                        start: 0,
+                        end: 6,
+                        line: 1,
+                        col: 0,
+                    },
+                },
+                Stmt::Assert {
+                    cond: binary(BinaryOp::Eq, name("x"), Expr::Integer(1)),
+                    message: Some(binary(
+                        BinaryOp::Add,
+                        Expr::String("x is ".to_string()),
+                        name("x"),
+                    )),
+                    span: Span {
+                        start: 12,
+                        end: 18,
+                        line: 2,
+                        col: 2,
+                    },
+                },
+            ])
+        );
+        assert!(parse_unspanned("assert;").is_err());
+        assert!(parse_unspanned("assert x,;").is_err());
+    }
+
+    #[test]
+    fn name_collector() {
+        use super::{parse, NameCollector, Visitor};
+
+        let stmts = parse(
+            "let a := b + 1; func f(x) { print x, c[d]; } \
+             while a < 3 { a := g(e) * -b; } for i in xs { }",
+        )
+        .unwrap();
+        let mut collector = NameCollector::default();
+        for stmt in &stmts {
+            collector.visit_stmt(stmt);
+        }
+
+        let names = collector
+            .names
+            .iter()
+            .map(|name| &name[..])
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b", "c", "d", "e", "x", "xs"]);
+    }
+
+    #[test]
+    fn fold_constants() {
+        use super::{fold_constants, Stmt};
+
+        let mut stmts = parse_unspanned("print 1 + 2 * 3, x + 1, -(2 ** 3) < 0;").unwrap();
+        fold_constants(&mut stmts);
+        assert_eq!(
+            stmts,
+            vec![Stmt::Print(vec![
+                Expr::Integer(7),
+                binary(BinaryOp::Add, name("x"), Expr::Integer(1)),
+                Expr::Boolean(true),
+            ])]
+        );
+
+        let mut stmts =
+            parse_unspanned("while x { print (1 + 1) / (2 - 2), \"a\" + \"b\", [1 + 1]; }")
+                .unwrap();
+        fold_constants(&mut stmts);
+        assert_eq!(
+            stmts,
+            vec![Stmt::While {
+                cond: name("x"),
+                body: vec![Stmt::Print(vec![
+                    binary(BinaryOp::Div, Expr::Integer(2), Expr::Integer(0)),
+                    Expr::String("ab".to_string()),
+                    Expr::ArrayLiteral(vec![Expr::Integer(2)]),
+                ])],
+            }]
+        );
+    }
+
+    #[test]
+    fn expression_spans() {
+        use super::{parse, UnaryOp};
+
+        assert_eq!(
+            parse("print 1 + x * 2, -f(y[0]);"),
+            Ok(vec![Stmt::Print(vec![
+                Expr::Binary {
+                    op: BinaryOp::Add,
+                    lhs: Box::new(Expr::Integer(1)),
+                    rhs: Box::new(Expr::Binary {
+                        op: BinaryOp::Mul,
+                        lhs: Box::new(name("x")),
+                        rhs: Box::new(Expr::Integer(2)),
+                        span: span(10, 15, 1, 10),
+                    }),
+                    span: span(6, 15, 1, 6),
+                },
+                Expr::Unary {
+                    op: UnaryOp::Neg,
+                    operand: Box::new(Expr::Call {
+                        name: "f".to_string(),
+                        args: vec![Expr::Index {
+                            target: Box::new(name("y")),
+                            index: Box::new(Expr::Integer(0)),
+                            span: span(20, 24, 1, 20),
+                        }],
+                        span: span(18, 25, 1, 18),
+                    }),
+                    span: span(17, 25, 1, 17),
+                },
+            ])])
+        );
+        assert_eq!(
+            parse("x := if a { 1 } else { 2 };"),
+            Ok(vec![Stmt::Assign {
+                name: "x".to_string(),
+                value: Expr::If {
+                    cond: Box::new(name("a")),
+                    then_branch: Box::new(Expr::Block {
+                        stmts: vec![],
+                        value: Box::new(Expr::Integer(1)),
+                        span: span(10, 15, 1, 10),
+                    }),
+                    else_branch: Box::new(Expr::Block {
+                        stmts: vec![],
+                        value: Box::new(Expr::Integer(2)),
+                        span: span(21, 26, 1, 21),
+                    }),
+                    span: span(5, 26, 1, 5),
+                },
+            }])
+        );
+    }
+
+    #[test]
+    fn chained_comparison() {
+        use super::{ParseError, Stmt};
+        use tokenizer::Span;
+
+        assert_eq!(
+            parse_unspanned("print 1 < 2 < 3;"),
+            Err(ParseError::ChainedComparison {
+                span: Span {
+                    start: 6,
+                    end: 13,
+                    line: 1,
+                    col: 6,
+                },
+            })
+        );
+        assert_eq!(
+            parse_unspanned("print x or a == b <> c;")
+                .unwrap_err()
+                .span(),
+            span(11, 20, 1, 11)
+        );
+        assert_eq!(
+            parse_unspanned("print (1 < 2) == true;"),
+            Ok(vec![Stmt::Print(vec![binary(
+                BinaryOp::Eq,
+                binary(BinaryOp::Lt, Expr::Integer(1), Expr::Integer(2)),
+                Expr::Boolean(true),
+            )])])
+        );
+    }
+}
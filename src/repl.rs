@@ -0,0 +1,74 @@
+//! An interactive read-eval-print loop.
+//!
+//! ```
+//! use rust_tmp::repl;
+//!
+//! let mut out = Vec::new();
+//! repl::run_repl(&b"let x := 6;\nprint x * 7;\n"[..], &mut out).unwrap();
+//! assert_eq!(out, b"42\n");
+//! ```
+
+use std::io::{self, BufRead, Write};
+
+use interpreter::Interpreter;
+use parser;
+
+/// Runs each line of `input` as a program, writing what it prints to
+/// `output`, until `input` is exhausted. Variables and functions carry over
+/// from one line to the next. Errors are written to `output` as well, and
+/// do not stop the loop; a line that fails partway leaves variables and
+/// functions as they were before it ran.
+pub fn run_repl<R: BufRead, W: Write>(input: R, output: W) -> io::Result<()> {
+    let mut interpreter = Interpreter::new(output);
+    for line in input.lines() {
+        let line = line?;
+        match parser::parse(&line) {
+            Ok(stmts) => {
+                let snapshot = interpreter.snapshot();
+                if let Err(err) = interpreter.eval(&stmts) {
+                    interpreter.restore(snapshot);
+                    writeln!(interpreter.output_mut(), "error: {}", err)?;
+                }
+            }
+            Err(err) => writeln!(interpreter.output_mut(), "error: {}", err)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_repl;
+
+    fn run(input: &str) -> String {
+        let mut out = Vec::new();
+        run_repl(input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn state_persists_between_lines() {
+        assert_eq!(run("let x := 2;\nprint x + 1;\n"), "3\n");
+    }
+
+    #[test]
+    fn errors_do_not_stop_the_loop() {
+        assert_eq!(
+            run("print y;\nlet y := ;\nlet y := 1;\nprint y;"),
+            "error: undefined variable `y`\n\
+             error: 1:9: unexpected ';'\n\
+             1\n"
+        );
+    }
+
+    #[test]
+    fn failed_lines_are_undone() {
+        assert_eq!(
+            run("let x := 1;\nx := 2; let y := x; print x; print 1 / 0;\nprint x;\nprint y;"),
+            "2\n\
+             error: division by zero\n\
+             1\n\
+             error: undefined variable `y`\n"
+        );
+    }
+}
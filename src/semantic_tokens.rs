@@ -0,0 +1,168 @@
+//! Classification of tokens in the form of the Language Server Protocol's
+//! semantic tokens, for highlighting in an editor.
+//!
+//! ```
+//! use rust_tmp::semantic_tokens::{semantic_tokens, TOKEN_TYPES};
+//!
+//! let tokens = semantic_tokens("print x;");
+//! assert_eq!(TOKEN_TYPES[tokens[0].token_type as usize], "keyword");
+//! assert_eq!(TOKEN_TYPES[tokens[1].token_type as usize], "variable");
+//! assert_eq!(tokens.len(), 2);
+//! ```
+
+use tokenizer::{Token, TokenKind, Tokenizer};
+
+/// The token types that `SemanticToken::token_type` indexes into, to be sent
+/// to the client as the legend of the semantic tokens provider.
+pub const TOKEN_TYPES: &[&str] = &[
+    "keyword", "operator", "number", "string", "variable", "comment",
+];
+
+const KEYWORD: u32 = 0;
+const OPERATOR: u32 = 1;
+const NUMBER: u32 = 2;
+const STRING: u32 = 3;
+const VARIABLE: u32 = 4;
+const COMMENT: u32 = 5;
+
+/// One highlighted range of source. Its position is relative to the start of
+/// the previous token, or of the input for the first one: `delta_line` counts
+/// lines down, and `delta_start` counts columns from the previous token's
+/// start if it is on the same line, or from the start of the line otherwise.
+/// Columns and lengths are in UTF-16 code units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub delta_line: u32,
+    pub delta_start: u32,
+    pub length: u32,
+    pub token_type: u32,
+    /// A bit set of modifiers. None are defined yet, so this is always 0.
+    pub modifiers: u32,
+}
+
+/// Classifies the tokens in `input`, comments included. Punctuation that
+/// only groups or separates, such as braces and commas, is left out, as is
+/// input that is not a valid token. A token spanning several lines, such as
+/// a block comment, is split into one range per line.
+pub fn semantic_tokens(input: &str) -> Vec<SemanticToken> {
+    let mut tokens = Vec::new();
+    let (mut prev_line, mut prev_start) = (0, 0);
+    for token in Tokenizer::new(input).with_comments().with_spans() {
+        let token_type = match token_type(&token.node) {
+            Some(token_type) => token_type,
+            None => continue,
+        };
+        let span = token.span;
+        let line_start = input[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let mut start = utf16_len(&input[line_start..span.start]);
+        let lines = input[span.start..span.end].split('\n');
+        for (line, text) in (span.line - 1..).zip(lines) {
+            let length = utf16_len(text.trim_end_matches('\r'));
+            if length > 0 {
+                tokens.push(SemanticToken {
+                    delta_line: line - prev_line,
+                    delta_start: if line == prev_line {
+                        start - prev_start
+                    } else {
+                        start
+                    },
+                    length,
+                    token_type,
+                    modifiers: 0,
+                });
+                prev_line = line;
+                prev_start = start;
+            }
+            start = 0;
+        }
+    }
+    tokens
+}
+
+/// Returns the index in `TOKEN_TYPES` of the type of `token`, or `None` if
+/// it is not highlighted.
+fn token_type(token: &Token) -> Option<u32> {
+    match token.kind() {
+        TokenKind::Keyword => Some(KEYWORD),
+        TokenKind::Operator => Some(OPERATOR),
+        TokenKind::Identifier => Some(VARIABLE),
+        TokenKind::Comment => Some(COMMENT),
+        TokenKind::Literal => match *token {
+            Token::Integer(_) | Token::Float(_) => Some(NUMBER),
+            Token::Char(_) | Token::StringLiteral(_) => Some(STRING),
+            // `true` and `false` are spelled like keywords.
+            _ => Some(KEYWORD),
+        },
+        TokenKind::Punctuation => match *token {
+            Token::BraceLeft
+            | Token::BraceRight
+            | Token::BracketLeft
+            | Token::BracketRight
+            | Token::ParenLeft
+            | Token::ParenRight
+            | Token::Colon
+            | Token::Comma
+            | Token::Dot
+            | Token::Semicolon => None,
+            _ => Some(OPERATOR),
+        },
+        TokenKind::Other => None,
+    }
+}
+
+fn utf16_len(s: &str) -> u32 {
+    s.encode_utf16().count() as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::{semantic_tokens, SemanticToken};
+
+    fn encode(input: &str) -> Vec<(u32, u32, u32, u32, u32)> {
+        semantic_tokens(input)
+            .iter()
+            .map(|token: &SemanticToken| {
+                (
+                    token.delta_line,
+                    token.delta_start,
+                    token.length,
+                    token.token_type,
+                    token.modifiers,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn two_lines() {
+        assert_eq!(
+            encode("let x := 10; // ten\n  print \"hi\", x + true;"),
+            vec![
+                (0, 0, 3, 0, 0),
+                (0, 4, 1, 4, 0),
+                (0, 2, 2, 1, 0),
+                (0, 3, 2, 2, 0),
+                (0, 4, 6, 5, 0),
+                (1, 2, 5, 0, 0),
+                (0, 6, 4, 3, 0),
+                (0, 6, 1, 4, 0),
+                (0, 2, 1, 1, 0),
+                (0, 2, 4, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_line_and_wide_tokens() {
+        assert_eq!(
+            encode("x /* a\r\n\n 😀 b */ \"😀\" y @"),
+            vec![
+                (0, 0, 1, 4, 0),
+                (0, 2, 4, 5, 0),
+                (2, 0, 8, 5, 0),
+                (0, 9, 4, 3, 0),
+                (0, 5, 1, 4, 0),
+            ]
+        );
+    }
+}
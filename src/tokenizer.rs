@@ -1,51 +1,327 @@
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
-use std::iter::{Iterator, Peekable};
-use std::str::Chars;
+//! Lexical analysis for ALAN source text.
+//!
+//! A `Tokenizer` is an iterator over the `Token`s of a borrowed string:
+//!
+//! ```
+//! use rust_tmp::tokenizer::{Token, Tokenizer};
+//!
+//! let tokens = Tokenizer::new("let x := 1;").collect::<Vec<_>>();
+//! assert!(tokens == vec![
+//!     Token::Let,
+//!     Token::Name("x".to_string()),
+//!     Token::Assign,
+//!     Token::Integer(1),
+//!     Token::Semicolon,
+//! ]);
+//! ```
 
-#[derive(PartialEq)]
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::iter::{FromIterator, Iterator};
+use std::slice;
+use std::vec;
+
+use parser;
+#[cfg(feature = "unicode-xid")]
+use unicode_xid::UnicodeXID;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Token {
-    Invalid,
+    /// Input that is not a valid token, given by its first char and that
+    /// char's line and column.
+    Invalid(char, u32, u32),
+    /// Marks the end of input, if requested with `Tokenizer::with_eof`.
+    Eof,
+    /// A line break, if requested with `Tokenizer::with_newlines`.
+    Newline,
+    /// The text of a `//` comment after the slashes, if requested with
+    /// `Tokenizer::with_comments`.
+    LineComment(String),
+    /// The text of a `/* */` comment between its delimiters, if requested
+    /// with `Tokenizer::with_comments`.
+    BlockComment(String),
+    /// A run of whitespace between tokens, if requested with
+    /// `Tokenizer::with_trivia`.
+    Whitespace(String),
 
     // Values
     Boolean(bool),
+    Char(char),
     Integer(i32),
+    Float(FloatLiteral),
     Name(String),
+    StringLiteral(String),
 
     // Keywords
     And,
     Array,
+    Assert,
+    Break,
+    Continue,
+    Else,
+    For,
+    Func,
     If,
+    In,
     Let,
     Not,
     Or,
     Print,
+    Return,
     While,
 
     // Symbols
+    /// `->`, reserved for return types and lambdas.
+    Arrow,
     Asterisk,
+    Bang,
     BraceLeft,
     BraceRight,
     BracketLeft,
     BracketRight,
     Colon,
+    Comma,
     Dot,
+    DoubleAsterisk,
     EqualSign,
     Minus,
     ParenLeft,
     ParenRight,
+    Percent,
     Plus,
     Semicolon,
     Slash,
 
     // Operators
     Assign,
+    AsteriskAssign,
     Eq,
     Ge,
     Gt,
     Le,
     Lt,
+    MinusAssign,
     Ne,
+    PlusAssign,
+    SlashAssign,
+}
+
+/// The broad category of a token, as a syntax highlighter would colour it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword,
+    Operator,
+    Literal,
+    Punctuation,
+    Identifier,
+    Comment,
+    /// `Invalid`, `Eof`, `Newline` and `Whitespace`, which belong to no
+    /// other category.
+    Other,
+}
+
+impl Token {
+    pub fn kind(&self) -> TokenKind {
+        match *self {
+            Token::Invalid(..) | Token::Eof | Token::Newline | Token::Whitespace(_) => {
+                TokenKind::Other
+            }
+            Token::LineComment(_) | Token::BlockComment(_) => TokenKind::Comment,
+
+            Token::Boolean(_)
+            | Token::Char(_)
+            | Token::Integer(_)
+            | Token::Float(_)
+            | Token::StringLiteral(_) => TokenKind::Literal,
+            Token::Name(_) => TokenKind::Identifier,
+
+            Token::And
+            | Token::Array
+            | Token::Break
+            | Token::Assert
+            | Token::Continue
+            | Token::Else
+            | Token::For
+            | Token::Func
+            | Token::If
+            | Token::In
+            | Token::Let
+            | Token::Not
+            | Token::Or
+            | Token::Print
+            | Token::Return
+            | Token::While => TokenKind::Keyword,
+
+            Token::Arrow
+            | Token::Asterisk
+            | Token::Bang
+            | Token::BraceLeft
+            | Token::BraceRight
+            | Token::BracketLeft
+            | Token::BracketRight
+            | Token::Colon
+            | Token::Comma
+            | Token::Dot
+            | Token::DoubleAsterisk
+            | Token::EqualSign
+            | Token::Minus
+            | Token::ParenLeft
+            | Token::ParenRight
+            | Token::Percent
+            | Token::Plus
+            | Token::Semicolon
+            | Token::Slash => TokenKind::Punctuation,
+
+            Token::Assign
+            | Token::AsteriskAssign
+            | Token::Eq
+            | Token::Ge
+            | Token::Gt
+            | Token::Le
+            | Token::Lt
+            | Token::MinusAssign
+            | Token::Ne
+            | Token::PlusAssign
+            | Token::SlashAssign => TokenKind::Operator,
+        }
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        self.kind() == TokenKind::Keyword
+    }
+
+    /// Returns whether the token can join two operands, as `+` and `and` do.
+    pub fn is_binary_op(&self) -> bool {
+        parser::binary_op(self).is_some()
+    }
+
+    /// Returns whether the token is one of the comparison operators, which
+    /// cannot be chained.
+    pub fn is_comparison(&self) -> bool {
+        parser::binary_op(self).is_some_and(|op| op.is_comparison())
+    }
+
+    /// Returns how tightly the token binds its left and right operands as a
+    /// binary operator, or `None` if it is not one. Higher binds tighter.
+    pub fn binding_power(&self) -> Option<(u8, u8)> {
+        parser::binary_op(self).map(parser::infix_binding_power)
+    }
+}
+
+/// Formats a token as it would be written in source.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Token::Invalid(..) => "<invalid>",
+            Token::Eof => "<eof>",
+            Token::Newline => "<newline>",
+            Token::LineComment(ref text) => return write!(f, "//{}", text),
+            Token::BlockComment(ref text) => return write!(f, "/*{}*/", text),
+            Token::Whitespace(ref text) => return f.write_str(text),
+
+            Token::Boolean(b) => return write!(f, "{}", b),
+            Token::Char(c) => {
+                f.write_str("'")?;
+                write_escaped(f, c, '\'')?;
+                return f.write_str("'");
+            }
+            Token::Integer(n) => return write!(f, "{}", n),
+            Token::Float(FloatLiteral(x)) => {
+                // Unlike `{:?}`, `{}` never uses exponent notation, which
+                // the tokenizer does not accept, but it leaves off the
+                // fraction of whole numbers.
+                let s = x.to_string();
+                return if s.contains('.') {
+                    f.write_str(&s)
+                } else {
+                    write!(f, "{}.0", s)
+                };
+            }
+            Token::Name(ref name) => name,
+            Token::StringLiteral(ref s) => {
+                f.write_str("\"")?;
+                for c in s.chars() {
+                    write_escaped(f, c, '"')?;
+                }
+                return f.write_str("\"");
+            }
+
+            Token::And => "and",
+            Token::Array => "array",
+            Token::Assert => "assert",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Else => "else",
+            Token::For => "for",
+            Token::Func => "func",
+            Token::If => "if",
+            Token::In => "in",
+            Token::Let => "let",
+            Token::Not => "not",
+            Token::Or => "or",
+            Token::Print => "print",
+            Token::Return => "return",
+            Token::While => "while",
+
+            Token::Arrow => "->",
+            Token::Asterisk => "*",
+            Token::Bang => "!",
+            Token::BraceLeft => "{",
+            Token::BraceRight => "}",
+            Token::BracketLeft => "[",
+            Token::BracketRight => "]",
+            Token::Colon => ":",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::DoubleAsterisk => "**",
+            Token::EqualSign => "=",
+            Token::Minus => "-",
+            Token::ParenLeft => "(",
+            Token::ParenRight => ")",
+            Token::Percent => "%",
+            Token::Plus => "+",
+            Token::Semicolon => ";",
+            Token::Slash => "/",
+
+            Token::Assign => ":=",
+            Token::AsteriskAssign => "*=",
+            Token::Eq => "==",
+            Token::Ge => ">=",
+            Token::Gt => ">",
+            Token::Le => "<=",
+            Token::Lt => "<",
+            Token::MinusAssign => "-=",
+            Token::Ne => "<>",
+            Token::PlusAssign => "+=",
+            Token::SlashAssign => "/=",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The value of a float literal. Unlike a bare `f64` it is `Eq` and `Hash`,
+/// so that `Token` can be too: two literals are equal only if their bits are.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FloatLiteral(pub f64);
+
+impl PartialEq for FloatLiteral {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for FloatLiteral {}
+
+impl Hash for FloatLiteral {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
 }
 
 lazy_static! {
@@ -53,252 +329,3087 @@ lazy_static! {
         let mut map = HashMap::new();
         map.insert("and", Token::And);
         map.insert("array", Token::Array);
+        map.insert("assert", Token::Assert);
+        map.insert("break", Token::Break);
+        map.insert("continue", Token::Continue);
+        map.insert("else", Token::Else);
+        map.insert("false", Token::Boolean(false));
+        map.insert("for", Token::For);
+        map.insert("func", Token::Func);
         map.insert("if", Token::If);
+        map.insert("in", Token::In);
         map.insert("let", Token::Let);
         map.insert("not", Token::Not);
         map.insert("or", Token::Or);
         map.insert("print", Token::Print);
+        map.insert("return", Token::Return);
+        map.insert("true", Token::Boolean(true));
         map.insert("while", Token::While);
         map
     };
 }
 
+/// The number of columns between tab stops unless set with
+/// `Tokenizer::with_tab_width`.
+pub const DEFAULT_TAB_WIDTH: u32 = 4;
+
+/// The longest name or number literal, in bytes, read unless set with
+/// `Tokenizer::with_max_token_len`.
+pub const DEFAULT_MAX_TOKEN_LEN: usize = 1024;
+
+/// Returns the keyword token spelled `name`, or `None` if `name` is not a
+/// keyword.
+pub fn keyword(name: &str) -> Option<Token> {
+    KEYWORDS.get(name).cloned()
+}
+
+/// Describes input that could not be read as a token. Positions count lines
+/// from 1 and columns from 0.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LexError {
+    /// A char that cannot begin any token.
+    UnexpectedChar { c: char, line: u32, col: u32 },
+    /// A backslash in a string literal followed by an unknown escape char.
+    InvalidEscape { c: char, line: u32, col: u32 },
+    /// A `\u` escape not of the form `\u{...}` with one to six hex digits,
+    /// or a `\x` escape not followed by two hex digits.
+    MalformedEscape { line: u32, col: u32 },
+    /// A `\u` escape naming a surrogate or a value above `10FFFF`.
+    InvalidCodePoint { value: u32, line: u32, col: u32 },
+    /// A string literal with no closing quote.
+    UnterminatedString { line: u32, col: u32 },
+    /// A char literal with nothing between its quotes.
+    EmptyChar { line: u32, col: u32 },
+    /// A char literal with more than one char between its quotes.
+    MultiChar { line: u32, col: u32 },
+    /// A char literal with no closing quote on the same line.
+    UnterminatedChar { line: u32, col: u32 },
+    /// A block comment with no closing `*/`.
+    UnterminatedComment { line: u32, col: u32 },
+    /// An integer literal too large to fit in an `i32`.
+    IntegerOverflow { text: String, line: u32, col: u32 },
+    /// A float literal too large to be represented as a finite `f64`.
+    FloatOverflow { text: String, line: u32, col: u32 },
+    /// A number literal with a radix prefix but no digits, with letters or
+    /// digits not allowed in its radix, or with an underscore that does not
+    /// separate two digits.
+    MalformedNumber { text: String, line: u32, col: u32 },
+    /// A name or number literal longer than the tokenizer's limit.
+    TokenTooLong { len: usize, line: u32, col: u32 },
+}
+
+impl LexError {
+    /// Returns the line and column at which the malformed input starts.
+    pub fn position(&self) -> (u32, u32) {
+        match *self {
+            LexError::UnexpectedChar { line, col, .. }
+            | LexError::InvalidEscape { line, col, .. }
+            | LexError::MalformedEscape { line, col }
+            | LexError::InvalidCodePoint { line, col, .. }
+            | LexError::UnterminatedString { line, col }
+            | LexError::EmptyChar { line, col }
+            | LexError::MultiChar { line, col }
+            | LexError::UnterminatedChar { line, col }
+            | LexError::UnterminatedComment { line, col }
+            | LexError::IntegerOverflow { line, col, .. }
+            | LexError::FloatOverflow { line, col, .. }
+            | LexError::MalformedNumber { line, col, .. }
+            | LexError::TokenTooLong { line, col, .. } => (line, col),
+        }
+    }
+
+    /// Returns the description of the error, without its position.
+    pub fn message(&self) -> String {
+        match *self {
+            LexError::UnexpectedChar { c, .. } => format!("unexpected char {:?}", c),
+            LexError::InvalidEscape { c, .. } => format!("unknown escape '\\{}'", c),
+            LexError::MalformedEscape { .. } => "malformed escape sequence".to_string(),
+            LexError::InvalidCodePoint { value, .. } => {
+                format!("'\\u{{{:X}}}' is not a valid char", value)
+            }
+            LexError::UnterminatedString { .. } => "unterminated string literal".to_string(),
+            LexError::EmptyChar { .. } => "empty char literal".to_string(),
+            LexError::MultiChar { .. } => "char literal holds more than one char".to_string(),
+            LexError::UnterminatedChar { .. } => "unterminated char literal".to_string(),
+            LexError::UnterminatedComment { .. } => "unterminated block comment".to_string(),
+            LexError::IntegerOverflow { ref text, .. } => {
+                format!("integer literal '{}' is too large", text)
+            }
+            LexError::FloatOverflow { ref text, .. } => {
+                format!("float literal '{}' is too large", text)
+            }
+            LexError::MalformedNumber { ref text, .. } => {
+                format!("malformed number literal '{}'", text)
+            }
+            LexError::TokenTooLong { len, .. } => format!("token is too long ({} bytes)", len),
+        }
+    }
+}
+
+/// Formats the error as `line:col: message`.
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (line, col) = self.position();
+        write!(f, "{}:{}: {}", line, col, self.message())
+    }
+}
+
+impl Error for LexError {}
+
+/// A range of source text, as byte offsets into the input.
+///
+/// `line` and `col` give the position of `start`, counting lines from 1 and
+/// columns from 0. A tab advances the column to the next tab stop.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl Span {
+    /// Returns the smallest span covering both `self` and `other`, such as
+    /// the span of a binary expression from those of its first and last
+    /// tokens.
+    pub fn merge(self, other: Span) -> Span {
+        let first = if other.start < self.start {
+            other
+        } else {
+            self
+        };
+        Span {
+            end: self.end.max(other.end),
+            ..first
+        }
+    }
+}
+
+/// A value tagged with the span of source it came from.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+/// Options for a `Tokenizer`, each of which can also be set with the
+/// tokenizer's builder method of the same name.
+///
+/// ```
+/// use rust_tmp::tokenizer::{Token, Tokenizer, TokenizerConfig};
+///
+/// let config = TokenizerConfig::default().with_newlines().with_eof();
+/// let tokens = Tokenizer::with_config("a\n", config).collect::<Vec<_>>();
+/// assert_eq!(
+///     tokens,
+///     vec![Token::Name("a".to_string()), Token::Newline, Token::Eof]
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenizerConfig {
+    /// Whether to yield `Token::Eof` at the end of input.
+    pub eof: bool,
+    /// Whether to yield line breaks as `Token::Newline`.
+    pub newlines: bool,
+    /// Whether to yield comments as tokens.
+    pub comments: bool,
+    /// Whether to yield whitespace and comments as tokens.
+    pub trivia: bool,
+    /// Whether keywords are recognised whatever their case.
+    pub case_insensitive_keywords: bool,
+    /// Whether to keep the errors replaced with `Token::Invalid`.
+    pub tolerant: bool,
+    /// The number of columns between tab stops, at least 1.
+    pub tab_width: u32,
+    /// The longest name or number literal to read, in bytes.
+    pub max_token_len: usize,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        TokenizerConfig {
+            eof: false,
+            newlines: false,
+            comments: false,
+            trivia: false,
+            case_insensitive_keywords: false,
+            tolerant: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            max_token_len: DEFAULT_MAX_TOKEN_LEN,
+        }
+    }
+}
+
+impl TokenizerConfig {
+    /// See `Tokenizer::with_eof`.
+    pub fn with_eof(self) -> Self {
+        TokenizerConfig { eof: true, ..self }
+    }
+
+    /// See `Tokenizer::with_newlines`.
+    pub fn with_newlines(self) -> Self {
+        TokenizerConfig {
+            newlines: true,
+            ..self
+        }
+    }
+
+    /// See `Tokenizer::with_comments`.
+    pub fn with_comments(self) -> Self {
+        TokenizerConfig {
+            comments: true,
+            ..self
+        }
+    }
+
+    /// See `Tokenizer::with_trivia`.
+    pub fn with_trivia(self) -> Self {
+        TokenizerConfig {
+            trivia: true,
+            comments: true,
+            ..self
+        }
+    }
+
+    /// See `Tokenizer::case_insensitive_keywords`.
+    pub fn case_insensitive_keywords(self) -> Self {
+        TokenizerConfig {
+            case_insensitive_keywords: true,
+            ..self
+        }
+    }
+
+    /// See `Tokenizer::tolerant`.
+    pub fn tolerant(self) -> Self {
+        TokenizerConfig {
+            tolerant: true,
+            ..self
+        }
+    }
+
+    /// See `Tokenizer::with_tab_width`.
+    pub fn with_tab_width(self, width: u32) -> Self {
+        TokenizerConfig {
+            tab_width: width.max(1),
+            ..self
+        }
+    }
+
+    /// See `Tokenizer::with_max_token_len`.
+    pub fn with_max_token_len(self, len: usize) -> Self {
+        TokenizerConfig {
+            max_token_len: len,
+            ..self
+        }
+    }
+}
+
+/// Reads tokens from source text. Cloning a tokenizer copies its position,
+/// so the copy can read ahead without disturbing the original.
+#[derive(Clone)]
 pub struct Tokenizer<'a> {
-    input: Peekable<Chars<'a>>,
-    len: usize,
-    pos: u32,
+    input: Cow<'a, str>,
+    /// The byte offset of the next char to be read from `input`.
+    pos: usize,
+    /// The byte offset of `input` in the whole source, which is more than 0
+    /// once input pushed with `push_str` has been read and discarded.
+    offset: usize,
+    /// Whether more input may yet be pushed with `push_str`.
+    more_input: bool,
     line: u32,
     col: u32,
+    config: TokenizerConfig,
+    /// Whether `input` is all ASCII, so that runs of chars can be scanned a
+    /// byte at a time without decoding UTF-8.
+    ascii: bool,
+    eof_emitted: bool,
+    errors: Vec<LexError>,
 }
 
 impl<'a> Tokenizer<'a> {
-    fn new(input: &'a str) -> Self {
-        let mut iter = input.chars().peekable();
+    pub fn new(input: &'a str) -> Self {
+        Tokenizer::from_cow(Cow::Borrowed(input))
+    }
+
+    /// Creates a tokenizer for `input` with the options in `config`.
+    pub fn with_config(input: &'a str, config: TokenizerConfig) -> Self {
+        Tokenizer {
+            config,
+            ..Tokenizer::new(input)
+        }
+    }
+
+    /// Reads all of `reader` and tokenizes it. Fails if reading fails or if
+    /// what is read is not valid UTF-8.
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Tokenizer<'static>> {
+        let mut input = String::new();
+        reader.read_to_string(&mut input)?;
+        Ok(Tokenizer::from_cow(Cow::Owned(input)))
+    }
+
+    /// Creates a tokenizer for source that arrives in chunks, which are
+    /// given to it with `push_str` and read with `next_ready`. Only the
+    /// input that has not been read yet is kept.
+    pub fn streaming() -> Tokenizer<'static> {
+        Tokenizer {
+            more_input: true,
+            ..Tokenizer::from_cow(Cow::Owned(String::new()))
+        }
+    }
+
+    /// Appends `chunk` to the input, discarding what has already been read.
+    pub fn push_str(&mut self, chunk: &str) {
+        let consumed = self.pos;
+        let input = self.input.to_mut();
+        input.drain(..consumed);
+        input.push_str(chunk);
+        self.ascii = input.is_ascii();
+        self.offset += consumed;
+        self.pos = 0;
+    }
+
+    /// Marks the input as complete, so that `next_ready` returns the tokens
+    /// it was holding back in case more input extended them.
+    pub fn finish(&mut self) {
+        self.more_input = false;
+    }
+
+    /// Returns the next token if all of its input has arrived, or `None` if
+    /// more is needed. Until `finish` is called, a token is held back while
+    /// more input could still change it, such as half of a name or an
+    /// unclosed string.
+    pub fn next_ready(&mut self) -> Option<Token> {
+        if !self.more_input {
+            return self.next();
+        }
+        let saved = (self.pos, self.line, self.col, self.eof_emitted);
+        let errors = self.errors.len();
+        match self.next_spanned() {
+            // The end of a token can depend on the two chars after it, as
+            // `1.` only starts a float if a digit follows.
+            Some(token)
+                if self.input[token.span.end - self.offset..]
+                    .chars()
+                    .nth(1)
+                    .is_some() =>
+            {
+                Some(token.node)
+            }
+            _ => {
+                (self.pos, self.line, self.col, self.eof_emitted) = saved;
+                self.errors.truncate(errors);
+                None
+            }
+        }
+    }
+
+    fn from_cow(input: Cow<'a, str>) -> Self {
         Tokenizer {
-            input: iter,
-            len: input.len(),
+            ascii: input.is_ascii(),
+            input,
             pos: 0,
+            offset: 0,
+            more_input: false,
             line: 1,
             col: 0,
+            config: TokenizerConfig::default(),
+            eof_emitted: false,
+            errors: Vec::new(),
         }
     }
 
-    fn next_char(&mut self) -> Option<char> {
-        self.input.next()
+    /// Makes the tokenizer yield a single `Token::Eof` once the input is
+    /// exhausted, before it starts returning `None`.
+    pub fn with_eof(mut self) -> Self {
+        self.config = self.config.with_eof();
+        self
     }
 
-    fn peek_char(&mut self) -> Option<&char> {
-        self.input.peek()
+    /// Makes the tokenizer yield a `Token::Newline` for each line break
+    /// rather than skipping it as whitespace.
+    pub fn with_newlines(mut self) -> Self {
+        self.config = self.config.with_newlines();
+        self
     }
 
-    fn consume_whitespace(&mut self) {
-        if let Some(&c) = self.peek_char() {
-            match c {
-                ' ' | '\t' | '\n' => {
-                    while let Some(&c) = self.peek_char() {
-                        match c {
-                            ' ' | '\t' | '\n' => {
-                                if c == '\n' {
-                                    self.line += 1
-                                };
-                                self.pos += 1;
-                                self.next_char();
-                            }
-                            _ => break,
-                        }
-                    }
-                }
-                _ => return,
-            }
+    /// Sets the number of columns between tab stops, which a tab advances the
+    /// column to. Defaults to `DEFAULT_TAB_WIDTH`; a width of 0 is taken as 1.
+    pub fn with_tab_width(mut self, width: u32) -> Self {
+        self.config = self.config.with_tab_width(width);
+        self
+    }
+
+    /// Sets the longest name or number literal, in bytes, that the tokenizer
+    /// reads. A longer one is skipped whole and reported as
+    /// `LexError::TokenTooLong`. Defaults to `DEFAULT_MAX_TOKEN_LEN`.
+    pub fn with_max_token_len(mut self, len: usize) -> Self {
+        self.config = self.config.with_max_token_len(len);
+        self
+    }
+
+    /// Makes the tokenizer recognise keywords whatever their case, so that
+    /// `WHILE` and `While` both read as `Token::While`. Other names keep
+    /// their case.
+    pub fn case_insensitive_keywords(mut self) -> Self {
+        self.config = self.config.case_insensitive_keywords();
+        self
+    }
+
+    /// Skips a `#!` interpreter line, as used to run a script directly, if
+    /// the input begins with one. Has no effect once reading has started.
+    pub fn skip_shebang(mut self) -> Self {
+        if self.pos == 0 && self.input.starts_with("#!") {
+            Tokenizer::skip_while(&mut self, |c| c != '\n');
         }
+        self
     }
-}
 
-impl<'a> Iterator for Tokenizer<'a> {
-    type Item = Token;
+    /// Makes the tokenizer yield each comment as a `Token::LineComment` or
+    /// `Token::BlockComment` rather than skipping it.
+    pub fn with_comments(mut self) -> Self {
+        self.config = self.config.with_comments();
+        self
+    }
 
-    fn next(&mut self) -> Option<Token> {
-        self.consume_whitespace();
-        if let Some(c) = self.next_char() {
-            let result = match c {
-                // Symbols
-                '*' => Token::Asterisk,
-                '{' => Token::BraceLeft,
-                '}' => Token::BraceRight,
-                '[' => Token::BracketLeft,
-                ']' => Token::BracketRight,
-                ':' => {
-                    match self.peek_char() {
-                        Some(&'=') => {
-                            self.next_char();
-                            Token::Assign
-                        }
-                        _ => Token::Colon,
-                    }
-                }
-                '.' => Token::Dot,
-                '=' => {
-                    match self.peek_char() {
-                        Some(&'=') => {
-                            self.next_char();
-                            Token::Eq
-                        }
-                        _ => Token::EqualSign,
-                    }
-                }
-                '-' => Token::Minus,
-                '(' => Token::ParenLeft,
-                ')' => Token::ParenRight,
-                '+' => Token::Plus,
-                ';' => Token::Semicolon,
-                '/' => Token::Slash,
-                '>' => {
-                    match self.peek_char() {
-                        Some(&'=') => {
-                            self.next_char();
-                            Token::Ge
-                        }
-                        _ => Token::Gt,
-                    }
-                }
-                '<' => {
-                    match self.peek_char() {
-                        Some(&'=') => {
-                            self.next_char();
-                            Token::Le
-                        }
-                        Some(&'>') => {
-                            self.next_char();
-                            Token::Ne
-                        }
-                        _ => Token::Lt,
-                    }
-                }
+    /// Makes the tokenizer yield everything between tokens as well, so that
+    /// the spans of the tokens cover the whole input and the source can be
+    /// rebuilt from them exactly, as a formatter needs. Runs of whitespace
+    /// are yielded as `Token::Whitespace`, and comments as with
+    /// `with_comments`.
+    pub fn with_trivia(mut self) -> Self {
+        self.config = self.config.with_trivia();
+        self
+    }
 
-                // Numbers
-                '0'...'9' => {
-                    let mut s = c.to_string();
-                    while let Some(&c) = self.peek_char() {
-                        match c {
-                            '0'...'9' => {
-                                s.push(c);
-                            }
-                            _ => break,
-                        };
-                        self.next_char();
-                    }
-                    let num = s.parse::<i32>().unwrap();
-                    Token::Integer(num)
-                }
+    /// Returns whether the tokenizer yields line breaks as `Token::Newline`.
+    pub fn emits_newlines(&self) -> bool {
+        self.config.newlines
+    }
 
-                // Alphanums
-                'a'...'z' | 'A'...'Z' => {
-                    let mut s = c.to_string();
-                    while let Some(&c) = self.peek_char() {
-                        match c {
-                            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => {
-                                s.push(c);
-                            }
-                            _ => break,
-                        };
-                        self.next_char();
-                    }
-                    match KEYWORDS.get(s.as_str()) {
-                        Some(&Token::And) => Token::And,
-                        Some(&Token::Array) => Token::Array,
-                        Some(&Token::If) => Token::If,
-                        Some(&Token::Let) => Token::Let,
-                        Some(&Token::Not) => Token::Not,
-                        Some(&Token::Or) => Token::Or,
-                        Some(&Token::Print) => Token::Print,
-                        Some(&Token::While) => Token::While,
-                        _ => Token::Name(s),
-                    }
-                }
+    /// Returns the options the tokenizer was set up with.
+    pub fn config(&self) -> TokenizerConfig {
+        self.config
+    }
 
-                // Anything else
-                _ => Token::Invalid,
-            };
-            Some(result)
+    /// Makes the tokenizer keep every error that it replaces with
+    /// `Token::Invalid`, to be read back with `errors`.
+    pub fn tolerant(mut self) -> Self {
+        self.config = self.config.tolerant();
+        self
+    }
+
+    /// Returns the errors found so far by a tokenizer made `tolerant`, in the
+    /// order they were found. Always empty otherwise.
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    /// Returns the line of the next char to be read, counting from 1.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Returns the column of the next char to be read, counting from 0.
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    /// Returns the empty span at the next char to be read.
+    pub fn cursor(&self) -> Span {
+        let start = self.offset + self.pos;
+        Span {
+            start,
+            end: start,
+            line: self.line,
+            col: self.col,
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let next = self.peek_char()?;
+        self.pos += next.len_utf8();
+        if next == '\n' {
+            self.line += 1;
+            self.col = 0;
         } else {
-            None
+            self.col = advance_col(self.col, next, self.config.tab_width);
         }
+        Some(next)
     }
-}
 
-#[cfg(test)]
-mod test {
-    #[test]
-    fn consume_whitespace() {
-        use super::Tokenizer;
-        let mut t = Tokenizer::new("t \t\na");
+    fn peek_char(&self) -> Option<char> {
+        if self.ascii {
+            return self.input.as_bytes().get(self.pos).map(|&b| b as char);
+        }
+        self.input[self.pos..].chars().next()
+    }
 
-        t.consume_whitespace();
-        assert!(t.peek_char() == Some(&'t'));
+    /// Looks at the char after the one `peek_char` would return.
+    fn peek_second(&self) -> Option<char> {
+        self.input[self.pos..].chars().nth(1)
+    }
 
-        t.next_char();
-        t.consume_whitespace();
-        assert!(t.peek_char() == Some(&'a'));
+    /// Skips the run of chars at the current position for which `pred`
+    /// holds. `pred` must not hold for a line break.
+    fn skip_while<F: Fn(char) -> bool>(&mut self, pred: F) {
+        if self.ascii {
+            let (mut col, tab_width) = (self.col, self.config.tab_width);
+            let len = self.input.as_bytes()[self.pos..]
+                .iter()
+                .take_while(|&&b| pred(b as char))
+                .inspect(|&&b| col = advance_col(col, b as char, tab_width))
+                .count();
+            self.pos += len;
+            self.col = col;
+            return;
+        }
+        while let Some(c) = self.peek_char() {
+            if !pred(c) {
+                break;
+            }
+            self.next_char();
+        }
+    }
 
-        t.next_char();
-        t.consume_whitespace();
-        assert!(t.peek_char() == None);
+    fn consume_whitespace(&mut self) {
+        loop {
+            self.skip_while(|c| c == ' ' || c == '\t' || c == '\r');
+            if self.config.newlines || self.peek_char() != Some('\n') {
+                break;
+            }
+            self.next_char();
+        }
     }
 
-    #[test]
-    fn symbol_tokens() {
-        use super::{Token, Tokenizer};
-        let mut t = Tokenizer::new("+-*/::=<<=");
+    /// Skips a `//` line comment, stopping before the newline that ends it,
+    /// or a `/* */` block comment. Returns whether a comment was skipped.
+    fn consume_comment(&mut self) -> Result<bool, LexError> {
+        if self.peek_char() != Some('/') {
+            return Ok(false);
+        }
+        let (line, col) = (self.line, self.col);
+        match self.peek_second() {
+            Some('/') => self.skip_while(|c| c != '\n'),
+            Some('*') => {
+                self.next_char();
+                self.next_char();
+                loop {
+                    match self.next_char() {
+                        Some('*') if self.peek_char() == Some('/') => {
+                            self.next_char();
+                            break;
+                        }
+                        Some(_) => {}
+                        None => return Err(LexError::UnterminatedComment { line, col }),
+                    }
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
 
-        assert!(t.next() == Some(Token::Plus));
-        assert!(t.next() == Some(Token::Minus));
-        assert!(t.next() == Some(Token::Asterisk));
-        assert!(t.next() == Some(Token::Slash));
-        assert!(t.next() == Some(Token::Colon));
-        assert!(t.next() == Some(Token::Assign));
-        assert!(t.next() == Some(Token::Lt));
-        assert!(t.next() == Some(Token::Le));
-        assert!(t.next() == None);
+    /// Reads the rest of a string literal whose opening quote has already
+    /// been consumed at `line` and `col`. After an unknown escape the literal
+    /// is still read up to its closing quote, so that lexing can resume after
+    /// it.
+    fn read_string(&mut self, line: u32, col: u32) -> Result<Token, LexError> {
+        let mut s = String::new();
+        let mut error = None;
+        loop {
+            let (escape_line, escape_col) = (self.line, self.col);
+            match self.next_char() {
+                Some('"') => break,
+                Some('\\') => match self.next_char() {
+                    Some(c) => match self.read_escape(c, escape_line, escape_col) {
+                        Ok(c) => s.push(c),
+                        Err(err) => {
+                            error.get_or_insert(err);
+                        }
+                    },
+                    None => return Err(LexError::UnterminatedString { line, col }),
+                },
+                Some(c) => s.push(c),
+                None => return Err(LexError::UnterminatedString { line, col }),
+            }
+        }
+        match error {
+            Some(err) => Err(err),
+            None => Ok(Token::StringLiteral(s)),
+        }
     }
 
-    #[test]
-    fn int_tokens() {
-        use super::{Token, Tokenizer};
-        let mut t = Tokenizer::new("1 2 3 123 987");
+    /// Reads the rest of a triple-quoted string literal whose opening quotes
+    /// have already been consumed at `line` and `col`. Its contents are taken
+    /// as written, line breaks included, with no escapes.
+    fn read_raw_string(&mut self, line: u32, col: u32) -> Result<Token, LexError> {
+        let start = self.pos;
+        while !self.input[self.pos..].starts_with("\"\"\"") {
+            if self.next_char().is_none() {
+                return Err(LexError::UnterminatedString { line, col });
+            }
+        }
+        let s = self.input[start..self.pos].to_string();
+        for _ in 0..3 {
+            self.next_char();
+        }
+        Ok(Token::StringLiteral(s))
+    }
 
-        assert!(t.next() == Some(Token::Integer(1)));
-        assert!(t.next() == Some(Token::Integer(2)));
-        assert!(t.next() == Some(Token::Integer(3)));
-        assert!(t.next() == Some(Token::Integer(123)));
-        assert!(t.next() == Some(Token::Integer(987)));
-        assert!(t.next() == None);
+    /// Reads the rest of a char literal whose opening quote has already been
+    /// consumed at `line` and `col`. A literal holding too many chars is read
+    /// up to its closing quote, and an unterminated one up to the end of the
+    /// line, so that lexing can resume after it.
+    fn read_char(&mut self, line: u32, col: u32) -> Result<Token, LexError> {
+        let (escape_line, escape_col) = (self.line, self.col);
+        let c = match self.next_char() {
+            None | Some('\n') => return Err(LexError::UnterminatedChar { line, col }),
+            Some('\'') => return Err(LexError::EmptyChar { line, col }),
+            Some('\\') => match self.next_char() {
+                Some(c) => self.read_escape(c, escape_line, escape_col),
+                None => return Err(LexError::UnterminatedChar { line, col }),
+            },
+            Some(c) => Ok(c),
+        };
+        if self.peek_char() == Some('\'') {
+            self.next_char();
+            return c.map(Token::Char);
+        }
+
+        while let Some(c) = self.peek_char() {
+            if c == '\n' {
+                break;
+            }
+            self.next_char();
+            if c == '\'' {
+                return Err(LexError::MultiChar { line, col });
+            }
+        }
+        Err(LexError::UnterminatedChar { line, col })
     }
 
-    #[test]
-    fn keyword_tokens() {
-        use super::{Token, Tokenizer};
-        let mut t = Tokenizer::new("and array if let not or print while");
+    /// Reads the rest of an escape sequence whose backslash is at `line` and
+    /// `col` and whose next char, `c`, has already been consumed.
+    fn read_escape(&mut self, c: char, line: u32, col: u32) -> Result<char, LexError> {
+        let value = match c {
+            'u' => {
+                if self.peek_char() != Some('{') {
+                    return Err(LexError::MalformedEscape { line, col });
+                }
+                self.next_char();
+                let digits = self.read_hex_digits(6);
+                if self.peek_char() != Some('}') || digits.is_empty() {
+                    return Err(LexError::MalformedEscape { line, col });
+                }
+                self.next_char();
+                u32::from_str_radix(&digits, 16)
+                    .map_err(|_| LexError::MalformedEscape { line, col })?
+            }
+            'x' => {
+                let digits = self.read_hex_digits(2);
+                if digits.len() != 2 {
+                    return Err(LexError::MalformedEscape { line, col });
+                }
+                u32::from_str_radix(&digits, 16)
+                    .map_err(|_| LexError::MalformedEscape { line, col })?
+            }
+            c => return unescape(c).ok_or(LexError::InvalidEscape { c, line, col }),
+        };
+        char::from_u32(value).ok_or(LexError::InvalidCodePoint { value, line, col })
+    }
 
-        assert!(t.next() == Some(Token::And));
-        assert!(t.next() == Some(Token::Array));
-        assert!(t.next() == Some(Token::If));
-        assert!(t.next() == Some(Token::Let));
-        assert!(t.next() == Some(Token::Not));
-        assert!(t.next() == Some(Token::Or));
-        assert!(t.next() == Some(Token::Print));
-        assert!(t.next() == Some(Token::While));
-        assert!(t.next() == None);
+    /// Reads up to `max` hex digits at the current position.
+    fn read_hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+        while let Some(c) = self.peek_char() {
+            if digits.len() == max || !c.is_ascii_hexdigit() {
+                break;
+            }
+            digits.push(c);
+            self.next_char();
+        }
+        digits
     }
 
-    #[test]
-    fn name_tokens() {
-        use super::{Token, Tokenizer};
-        let mut t = Tokenizer::new("and xxx if If");
+    /// Returns the text from byte `start` to the current position, or
+    /// `LexError::TokenTooLong` if it is longer than the configured limit.
+    fn token_text(&self, start: usize, line: u32, col: u32) -> Result<String, LexError> {
+        let len = self.pos - start;
+        if len > self.config.max_token_len {
+            return Err(LexError::TokenTooLong { len, line, col });
+        }
+        Ok(self.input[start..self.pos].to_string())
+    }
+
+    /// Reads a decimal integer or float literal starting with the digit `c`,
+    /// which has already been consumed at `line` and `col`.
+    fn read_decimal(&mut self, c: char, line: u32, col: u32) -> Result<Token, LexError> {
+        let start = self.pos - c.len_utf8();
+        let is_digit = |c: char| c.is_ascii_digit() || c == '_';
+        self.skip_while(is_digit);
+        // Only a dot followed by a digit starts a fraction, so that `1.foo`
+        // still lexes the dot separately.
+        let fraction =
+            self.peek_char() == Some('.') && matches!(self.peek_second(), Some('0'..='9'));
+        if fraction {
+            self.next_char();
+            self.skip_while(is_digit);
+        }
+        let text = self.token_text(start, line, col)?;
+        if !text.split('.').all(valid_separators) {
+            return Err(LexError::MalformedNumber { text, line, col });
+        }
+
+        let digits = text.replace('_', "");
+        if fraction {
+            match digits.parse::<f64>() {
+                Ok(f) if f.is_finite() => Ok(Token::Float(FloatLiteral(f))),
+                Ok(_) => Err(LexError::FloatOverflow { text, line, col }),
+                Err(_) => Err(LexError::MalformedNumber { text, line, col }),
+            }
+        } else {
+            match digits.parse::<i32>() {
+                Ok(n) => Ok(Token::Integer(n)),
+                Err(_) => Err(LexError::IntegerOverflow { text, line, col }),
+            }
+        }
+    }
+
+    /// Reads a hexadecimal or binary integer literal, the leading `0` having
+    /// been consumed at `line` and `col` and the radix char being next. Any
+    /// letters or digits running on from the literal are reported as part of
+    /// it rather than starting a new token.
+    fn read_radix_integer(&mut self, radix: u32, line: u32, col: u32) -> Result<Token, LexError> {
+        let start = self.pos - 1;
+        self.next_char();
+        let mut malformed = false;
+        while let Some(c) = self.peek_char() {
+            if c.is_digit(radix) {
+                // A digit, collected below.
+            } else if c.is_ascii_alphanumeric() {
+                malformed = true;
+            } else if c == '_' {
+                // A digit separator, checked below.
+            } else {
+                break;
+            }
+            self.next_char();
+        }
+        let text = self.token_text(start, line, col)?;
+        let digits = text[2..].replace('_', "");
+        if malformed || digits.is_empty() || !valid_separators(&text[2..]) {
+            return Err(LexError::MalformedNumber { text, line, col });
+        }
+        match i32::from_str_radix(&digits, radix) {
+            Ok(n) => Ok(Token::Integer(n)),
+            Err(_) => Err(LexError::IntegerOverflow { text, line, col }),
+        }
+    }
+
+    /// Reads the token that begins with `c`, which has already been consumed
+    /// at `line` and `col`.
+    fn read_token(&mut self, c: char, line: u32, col: u32) -> Result<Token, LexError> {
+        let token = match c {
+            // Only reached when line breaks are not skipped as whitespace.
+            '\n' => Token::Newline,
+
+            // Symbols
+            '*' => match self.peek_char() {
+                Some('*') => {
+                    self.next_char();
+                    Token::DoubleAsterisk
+                }
+                Some('=') => {
+                    self.next_char();
+                    Token::AsteriskAssign
+                }
+                _ => Token::Asterisk,
+            },
+            '!' => match self.peek_char() {
+                Some('=') => {
+                    self.next_char();
+                    Token::Ne
+                }
+                _ => Token::Bang,
+            },
+            '{' => Token::BraceLeft,
+            '}' => Token::BraceRight,
+            '[' => Token::BracketLeft,
+            ']' => Token::BracketRight,
+            ':' => match self.peek_char() {
+                Some('=') => {
+                    self.next_char();
+                    Token::Assign
+                }
+                _ => Token::Colon,
+            },
+            ',' => Token::Comma,
+            '.' => Token::Dot,
+            '=' => match self.peek_char() {
+                Some('=') => {
+                    self.next_char();
+                    Token::Eq
+                }
+                _ => Token::EqualSign,
+            },
+            '-' => match self.peek_char() {
+                Some('=') => {
+                    self.next_char();
+                    Token::MinusAssign
+                }
+                Some('>') => {
+                    self.next_char();
+                    Token::Arrow
+                }
+                _ => Token::Minus,
+            },
+            '(' => Token::ParenLeft,
+            ')' => Token::ParenRight,
+            '%' => Token::Percent,
+            '+' => match self.peek_char() {
+                Some('=') => {
+                    self.next_char();
+                    Token::PlusAssign
+                }
+                _ => Token::Plus,
+            },
+            ';' => Token::Semicolon,
+            '/' => match self.peek_char() {
+                Some('=') => {
+                    self.next_char();
+                    Token::SlashAssign
+                }
+                _ => Token::Slash,
+            },
+            '>' => match self.peek_char() {
+                Some('=') => {
+                    self.next_char();
+                    Token::Ge
+                }
+                _ => Token::Gt,
+            },
+            '<' => match self.peek_char() {
+                Some('=') => {
+                    self.next_char();
+                    Token::Le
+                }
+                Some('>') => {
+                    self.next_char();
+                    Token::Ne
+                }
+                _ => Token::Lt,
+            },
+
+            // Strings
+            '"' => {
+                if self.peek_char() == Some('"') && self.peek_second() == Some('"') {
+                    self.next_char();
+                    self.next_char();
+                    return self.read_raw_string(line, col);
+                }
+                return self.read_string(line, col);
+            }
+            '\'' => return self.read_char(line, col),
+
+            // Numbers
+            '0'..='9' => {
+                if c == '0' {
+                    match self.peek_char() {
+                        Some('x') | Some('X') => return self.read_radix_integer(16, line, col),
+                        Some('b') | Some('B') => return self.read_radix_integer(2, line, col),
+                        _ => {}
+                    }
+                }
+                return self.read_decimal(c, line, col);
+            }
+
+            // Alphanums
+            c if is_name_start(c) => {
+                let start = self.pos - c.len_utf8();
+                self.skip_while(is_name_continue);
+                let s = self.token_text(start, line, col)?;
+                let keyword = if self.config.case_insensitive_keywords {
+                    keyword(&s.to_ascii_lowercase())
+                } else {
+                    keyword(&s)
+                };
+                keyword.unwrap_or(Token::Name(s))
+            }
+
+            // Anything else
+            _ => return Err(LexError::UnexpectedChar { c, line, col }),
+        };
+        Ok(token)
+    }
+
+    /// Reads the next token and the span it covers, skipping whitespace and
+    /// comments.
+    fn lex(&mut self) -> Option<(Span, Result<Token, LexError>)> {
+        loop {
+            let (start, line, col) = (self.pos, self.line, self.col);
+            self.consume_whitespace();
+            if self.config.trivia && self.pos > start {
+                let span = Span {
+                    start: start + self.offset,
+                    end: self.pos + self.offset,
+                    line,
+                    col,
+                };
+                let text = self.input[start..self.pos].to_string();
+                return Some((span, Ok(Token::Whitespace(text))));
+            }
+            let (start, line, col) = (self.pos, self.line, self.col);
+            let result = match self.consume_comment() {
+                Ok(true) if self.config.comments => Ok(comment(&self.input[start..self.pos])),
+                Ok(true) => continue,
+                Ok(false) => match self.next_char() {
+                    Some(c) => self.read_token(c, line, col),
+                    None if self.config.eof && !self.eof_emitted => {
+                        self.eof_emitted = true;
+                        Ok(Token::Eof)
+                    }
+                    None => return None,
+                },
+                Err(err) => Err(err),
+            };
+            let span = Span {
+                start: start + self.offset,
+                end: self.pos + self.offset,
+                line,
+                col,
+            };
+            return Some((span, result));
+        }
+    }
+
+    /// Returns the next token along with the span of input it was read from,
+    /// or `None` once the input is exhausted.
+    pub fn next_spanned(&mut self) -> Option<Spanned<Token>> {
+        let (span, result) = self.lex()?;
+        let node = match result {
+            Ok(token) => token,
+            Err(err) => {
+                if self.config.tolerant {
+                    self.errors.push(err);
+                }
+                // Every error consumes at least the char it starts at.
+                let c = self.input[span.start - self.offset..]
+                    .chars()
+                    .next()
+                    .unwrap_or('\0');
+                Token::Invalid(c, span.line, span.col)
+            }
+        };
+        Some(Spanned { node, span })
+    }
+
+    /// Returns the next token, or the error describing why the input at this
+    /// point is not a valid token.
+    pub fn try_next(&mut self) -> Option<Result<Token, LexError>> {
+        self.lex().map(|(_, result)| result)
+    }
+
+    /// Adapts this tokenizer into an iterator that yields a `LexError` for
+    /// malformed input rather than `Token::Invalid`.
+    pub fn checked(self) -> Checked<'a> {
+        Checked { inner: self }
+    }
+
+    /// Adapts this tokenizer into an iterator over spanned tokens.
+    pub fn with_spans(self) -> WithSpans<'a> {
+        WithSpans { inner: self }
+    }
+
+    /// Adapts this tokenizer into an iterator of `(start, token, end)`
+    /// triples of byte offsets, the form parser generators such as LALRPOP
+    /// expect from an external lexer.
+    pub fn spanned(self) -> impl Iterator<Item = (usize, Token, usize)> + 'a {
+        self.with_spans()
+            .map(|spanned| (spanned.span.start, spanned.node, spanned.span.end))
+    }
+}
+
+/// Iterates over tokens, replacing any malformed input with `Token::Invalid`.
+/// Use `Tokenizer::checked` to find out what went wrong.
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.next_spanned().map(|spanned| spanned.node)
+    }
+
+    /// Every token but `Token::Eof` takes up at least one byte of input, so
+    /// there can be no more tokens left than there are bytes.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let eof = (self.config.eof && !self.eof_emitted) as usize;
+        (eof, Some(self.input.len() - self.pos + eof))
+    }
+}
+
+/// Reads all the tokens in `input`, with `Token::Invalid` in place of any
+/// malformed input.
+///
+/// ```
+/// use rust_tmp::tokenizer::{tokens, Token};
+///
+/// assert_eq!(
+///     tokens("1 + 2"),
+///     vec![Token::Integer(1), Token::Plus, Token::Integer(2)]
+/// );
+/// ```
+pub fn tokens(input: &str) -> TokenList {
+    Tokenizer::new(input).collect()
+}
+
+/// A sequence of tokens, as collected from a `Tokenizer`.
+///
+/// ```
+/// use rust_tmp::tokenizer::{TokenKind, TokenList, Tokenizer};
+///
+/// let list: TokenList = Tokenizer::new("1+2").collect();
+/// assert_eq!(list.len(), 3);
+/// assert_eq!(
+///     list.kinds().collect::<Vec<_>>(),
+///     vec![TokenKind::Literal, TokenKind::Punctuation, TokenKind::Literal]
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct TokenList(Vec<Token>);
+
+impl TokenList {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Token> {
+        self.0.iter()
+    }
+
+    /// Returns the kind of each token in turn.
+    pub fn kinds<'a>(&'a self) -> impl Iterator<Item = TokenKind> + 'a {
+        self.0.iter().map(Token::kind)
+    }
+
+    pub fn as_slice(&self) -> &[Token] {
+        &self.0
+    }
+
+    pub fn into_vec(self) -> Vec<Token> {
+        self.0
+    }
+}
+
+impl FromIterator<Token> for TokenList {
+    fn from_iter<I: IntoIterator<Item = Token>>(iter: I) -> Self {
+        TokenList(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for TokenList {
+    type Item = Token;
+    type IntoIter = vec::IntoIter<Token>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TokenList {
+    type Item = &'a Token;
+    type IntoIter = slice::Iter<'a, Token>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl From<Vec<Token>> for TokenList {
+    fn from(tokens: Vec<Token>) -> Self {
+        TokenList(tokens)
+    }
+}
+
+impl PartialEq<Vec<Token>> for TokenList {
+    fn eq(&self, other: &Vec<Token>) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<TokenList> for Vec<Token> {
+    fn eq(&self, other: &TokenList) -> bool {
+        *self == other.0
+    }
+}
+
+/// Reads all the tokens in `input` along with their spans, or returns the
+/// first error found.
+pub fn analyze(input: &str) -> Result<Vec<Spanned<Token>>, LexError> {
+    let mut tokenizer = Tokenizer::new(input);
+    let mut tokens = Vec::new();
+    while let Some((span, result)) = tokenizer.lex() {
+        tokens.push(Spanned {
+            node: result?,
+            span,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Formats each token in `input` with the line and column it starts at, for
+/// debugging.
+pub fn dump(input: &str) -> String {
+    Tokenizer::new(input)
+        .with_spans()
+        .map(|token| format!("[{}:{} {:?}]", token.span.line, token.span.col, token.node))
+        .collect()
+}
+
+/// Reads all the tokens in `input`, or returns the first error found.
+pub fn try_tokens(input: &str) -> Result<Vec<Token>, LexError> {
+    Tokenizer::new(input).checked().collect()
+}
+
+/// Writes `tokens` out as source text that reads back as the same tokens,
+/// with single spaces between them and `Token::Newline` as a line break. A
+/// line comment is ended with a line break if another token follows it. An
+/// `Eof` writes nothing, and an `Invalid` token cannot be reproduced.
+/// `Whitespace` is written as it is, in place of a separating space.
+pub fn to_source(tokens: &[Token]) -> String {
+    let mut source = String::new();
+    let mut in_line_comment = false;
+    let mut separated = true;
+    for token in tokens {
+        match *token {
+            Token::Eof => continue,
+            Token::Newline => {
+                source.push('\n');
+                in_line_comment = false;
+                separated = true;
+                continue;
+            }
+            Token::Whitespace(ref text) => {
+                source.push_str(text);
+                in_line_comment &= !text.contains('\n');
+                separated = true;
+                continue;
+            }
+            _ => {}
+        }
+        if in_line_comment {
+            source.push('\n');
+        } else if !separated {
+            source.push(' ');
+        }
+        source.push_str(&token.to_string());
+        in_line_comment = matches!(*token, Token::LineComment(_));
+        separated = false;
+    }
+    source
+}
+
+/// Returns whether `c` can begin a name. Without the `unicode-xid` feature
+/// names are ASCII only.
+#[cfg(not(feature = "unicode-xid"))]
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+#[cfg(not(feature = "unicode-xid"))]
+fn is_name_continue(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Returns whether `c` can begin a name: a char with the Unicode
+/// `XID_Start` property, or `_`.
+#[cfg(feature = "unicode-xid")]
+fn is_name_start(c: char) -> bool {
+    c.is_xid_start() || c == '_'
+}
+
+#[cfg(feature = "unicode-xid")]
+fn is_name_continue(c: char) -> bool {
+    c.is_xid_continue()
+}
+
+/// Returns the column after `c`, which starts at `col` on its line.
+fn advance_col(col: u32, c: char, tab_width: u32) -> u32 {
+    if c == '\t' {
+        (col / tab_width + 1) * tab_width
+    } else {
+        col + 1
+    }
+}
+
+/// Returns the token for the comment `text`, delimiters included.
+fn comment(text: &str) -> Token {
+    match text.strip_prefix("//") {
+        Some(text) => Token::LineComment(text.to_string()),
+        None => Token::BlockComment(text[2..text.len() - 2].to_string()),
+    }
+}
+
+/// Returns the char that a backslash followed by `c` stands for in a string
+/// or char literal.
+fn unescape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        '\\' | '"' | '\'' => Some(c),
+        _ => None,
+    }
+}
+
+/// Writes `c` as it would appear in a literal delimited by `quote`.
+fn write_escaped(f: &mut fmt::Formatter, c: char, quote: char) -> fmt::Result {
+    match c {
+        '\n' => f.write_str("\\n"),
+        '\t' => f.write_str("\\t"),
+        '\\' => f.write_str("\\\\"),
+        c if c == quote => write!(f, "\\{}", c),
+        c => write!(f, "{}", c),
+    }
+}
+
+/// Checks that the underscores in a run of digits only ever separate two
+/// digits.
+fn valid_separators(digits: &str) -> bool {
+    !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+}
+
+/// Iterator over the spanned tokens of a `Tokenizer`, created by
+/// `Tokenizer::with_spans`.
+#[derive(Clone)]
+pub struct WithSpans<'a> {
+    inner: Tokenizer<'a>,
+}
+
+impl<'a> WithSpans<'a> {
+    /// Returns the tokenizer the tokens are read from, which stands just
+    /// after the last token read.
+    pub fn tokenizer(&self) -> &Tokenizer<'a> {
+        &self.inner
+    }
+}
+
+impl<'a> Iterator for WithSpans<'a> {
+    type Item = Spanned<Token>;
+
+    fn next(&mut self) -> Option<Spanned<Token>> {
+        self.inner.next_spanned()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Iterator over the tokens of a `Tokenizer` or the errors found in their
+/// place, created by `Tokenizer::checked`.
+pub struct Checked<'a> {
+    inner: Tokenizer<'a>,
+}
+
+impl<'a> Iterator for Checked<'a> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Result<Token, LexError>> {
+        self.inner.try_next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Wraps a `Tokenizer` to allow looking any number of tokens ahead without
+/// consuming them.
+pub struct PeekableTokenizer<'a> {
+    inner: Tokenizer<'a>,
+    buffer: VecDeque<Token>,
+}
+
+impl<'a> PeekableTokenizer<'a> {
+    pub fn new(inner: Tokenizer<'a>) -> Self {
+        PeekableTokenizer {
+            inner,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Returns the next token without consuming it.
+    pub fn peek(&mut self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    /// Returns the token `n` places ahead without consuming anything, so
+    /// that `peek_nth(0)` is the token `next` would return. Returns `None`
+    /// if the input ends first.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        while self.buffer.len() <= n {
+            match self.inner.next() {
+                Some(token) => self.buffer.push_back(token),
+                None => return None,
+            }
+        }
+        self.buffer.get(n)
+    }
+}
+
+impl<'a> Iterator for PeekableTokenizer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.buffer.pop_front().or_else(|| self.inner.next())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Span;
+
+    fn span(start: usize, end: usize, line: u32, col: u32) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            col,
+        }
+    }
+
+    /// Checks the tokens of `source` against `golden`, which lists the `Debug`
+    /// form of each token on a line of its own. Indentation and blank lines
+    /// in `golden` are ignored.
+    fn assert_golden(source: &str, golden: &str) {
+        let actual = super::tokens(source)
+            .iter()
+            .map(|token| format!("{:?}\n", token))
+            .collect::<String>();
+        let expected = golden
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        assert_eq!(actual, expected, "tokens of {:?}", source);
+    }
+
+    #[test]
+    fn golden_program() {
+        assert_golden(
+            "func f(n) { if n <= 1 { return 1; } return n * f(n - 1); }\n\
+             let xs := [f(5), 0x1F, 2.5, 'c'];\n\
+             for x in xs { print x, \"x\" + \"y\"; } // done",
+            r#"
+            Func
+            Name("f")
+            ParenLeft
+            Name("n")
+            ParenRight
+            BraceLeft
+            If
+            Name("n")
+            Le
+            Integer(1)
+            BraceLeft
+            Return
+            Integer(1)
+            Semicolon
+            BraceRight
+            Return
+            Name("n")
+            Asterisk
+            Name("f")
+            ParenLeft
+            Name("n")
+            Minus
+            Integer(1)
+            ParenRight
+            Semicolon
+            BraceRight
+            Let
+            Name("xs")
+            Assign
+            BracketLeft
+            Name("f")
+            ParenLeft
+            Integer(5)
+            ParenRight
+            Comma
+            Integer(31)
+            Comma
+            Float(FloatLiteral(2.5))
+            Comma
+            Char('c')
+            BracketRight
+            Semicolon
+            For
+            Name("x")
+            In
+            Name("xs")
+            BraceLeft
+            Print
+            Name("x")
+            Comma
+            StringLiteral("x")
+            Plus
+            StringLiteral("y")
+            Semicolon
+            BraceRight
+            "#,
+        );
+    }
+
+    #[test]
+    fn merge_spans() {
+        assert_eq!(span(0, 1, 1, 0).merge(span(4, 5, 1, 4)), span(0, 5, 1, 0));
+        assert_eq!(span(4, 5, 2, 1).merge(span(0, 1, 1, 0)), span(0, 5, 1, 0));
+        assert_eq!(span(2, 9, 1, 2).merge(span(3, 4, 1, 3)), span(2, 9, 1, 2));
+    }
+
+    #[test]
+    fn consume_whitespace() {
+        use super::Tokenizer;
+        let mut t = Tokenizer::new("t \t\na");
+
+        t.consume_whitespace();
+        assert!(t.peek_char() == Some('t'));
+
+        t.next_char();
+        t.consume_whitespace();
+        assert!(t.peek_char() == Some('a'));
+
+        t.next_char();
+        t.consume_whitespace();
+        assert!(t.peek_char().is_none());
+    }
+
+    #[test]
+    fn config() {
+        use super::{Token, Tokenizer, TokenizerConfig, DEFAULT_TAB_WIDTH};
+
+        let config = TokenizerConfig::default();
+        assert_eq!(config.tab_width, DEFAULT_TAB_WIDTH);
+        assert!(!config.newlines && !config.comments && !config.tolerant);
+        assert_eq!(Tokenizer::new("").config(), config);
+
+        let config = TokenizerConfig::default()
+            .case_insensitive_keywords()
+            .with_comments()
+            .tolerant()
+            .with_tab_width(2);
+        let mut t = Tokenizer::with_config("WHILE\t// x\n@", config).with_spans();
+        assert_eq!(t.next().map(|token| token.node), Some(Token::While));
+        let comment = t.next().unwrap();
+        assert_eq!(comment.node, Token::LineComment(" x".to_string()));
+        assert_eq!(comment.span.col, 6);
+        assert_eq!(
+            t.next().map(|token| token.node),
+            Some(Token::Invalid('@', 2, 0))
+        );
+        assert_eq!(t.inner.errors().len(), 1);
+
+        // The builder methods on a tokenizer set the same options.
+        assert_eq!(
+            Tokenizer::new("").with_newlines().with_eof().config(),
+            TokenizerConfig {
+                newlines: true,
+                eof: true,
+                ..TokenizerConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn tab_width() {
+        use super::{Token, Tokenizer};
+
+        let mut t = Tokenizer::new("\tx").with_tab_width(1).with_spans();
+        assert_eq!(t.next().map(|token| token.span.col), Some(1));
+
+        let mut t = Tokenizer::new("\tx").with_spans();
+        assert_eq!(t.next().map(|token| token.span.col), Some(4));
+
+        // A tab advances to the next tab stop, not by a fixed amount.
+        let mut t = Tokenizer::new("ab\tx\n\t\ty").with_spans();
+        assert_eq!(t.nth(1).map(|token| token.span.col), Some(4));
+        assert_eq!(t.next().map(|token| token.span.col), Some(8));
+
+        // Input that is not all ASCII, and so is scanned char by char, counts
+        // the same way.
+        let mut t = Tokenizer::new("é \t\tx").with_tab_width(3).with_spans();
+        assert_eq!(
+            t.nth(1).map(|token| (token.node, token.span.col)),
+            Some((Token::Name("x".to_string()), 6))
+        );
+    }
+
+    #[test]
+    fn max_token_len() {
+        use super::{LexError, Token, Tokenizer};
+
+        // An overlong token is skipped whole, so tokenizing carries on after it.
+        let source = format!("{} x", "a".repeat(2000));
+        let mut t = Tokenizer::new(&source).checked();
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::TokenTooLong {
+                len: 2000,
+                line: 1,
+                col: 0,
+            }))
+        );
+        assert_eq!(t.next(), Some(Ok(Token::Name("x".to_string()))));
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new("1234 123.5 0x1f 12345")
+            .with_max_token_len(4)
+            .checked();
+        assert_eq!(t.next(), Some(Ok(Token::Integer(1234))));
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::TokenTooLong {
+                len: 5,
+                line: 1,
+                col: 5,
+            }))
+        );
+        assert_eq!(t.next(), Some(Ok(Token::Integer(0x1f))));
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::TokenTooLong {
+                len: 5,
+                line: 1,
+                col: 16,
+            }))
+        );
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn newline_tokens() {
+        use super::{Token, Tokenizer};
+
+        let tokens = Tokenizer::new("print 1\nprint 2").collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Print,
+                Token::Integer(1),
+                Token::Print,
+                Token::Integer(2),
+            ]
+        );
+
+        let mut t = Tokenizer::new("print 1\nprint 2 // two\r\n").with_newlines();
+        assert!(t.emits_newlines());
+        let tokens = t.by_ref().collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Print,
+                Token::Integer(1),
+                Token::Newline,
+                Token::Print,
+                Token::Integer(2),
+                Token::Newline,
+            ]
+        );
+        assert_eq!(t.line, 3);
+    }
+
+    #[test]
+    fn leading_whitespace() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("   x");
+
+        t.consume_whitespace();
+        assert_eq!(t.pos, 3);
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn from_reader() {
+        use super::Tokenizer;
+        use std::io::{Cursor, ErrorKind};
+
+        let reader = Cursor::new(b"let x := 1;".to_vec());
+        assert_eq!(
+            Tokenizer::from_reader(reader).unwrap().collect::<Vec<_>>(),
+            Tokenizer::new("let x := 1;").collect::<Vec<_>>()
+        );
+
+        let reader = Cursor::new(vec![b'x', 0xff]);
+        let err = Tokenizer::from_reader(reader).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn line_comments() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("let x := 1; // set x\nprint x; //\n// done");
+
+        assert_eq!(t.next(), Some(Token::Let));
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        assert_eq!(t.next(), Some(Token::Assign));
+        assert_eq!(t.next(), Some(Token::Integer(1)));
+        assert_eq!(t.next(), Some(Token::Semicolon));
+        assert_eq!(
+            t.next_spanned().map(|s| (s.node, s.span.line)),
+            Some((Token::Print, 2))
+        );
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        assert_eq!(t.next(), Some(Token::Semicolon));
+        assert_eq!(t.next(), None);
+        assert_eq!(t.line, 3);
+    }
+
+    #[test]
+    fn block_comments() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("a /* one\ntwo */ b/**/c /* x /* y */ d */");
+
+        assert_eq!(t.next(), Some(Token::Name("a".to_string())));
+        assert_eq!(
+            t.next_spanned().map(|s| (s.node, s.span.line)),
+            Some((Token::Name("b".to_string()), 2))
+        );
+        assert_eq!(t.next(), Some(Token::Name("c".to_string())));
+        assert_eq!(t.next(), Some(Token::Name("d".to_string())));
+        assert_eq!(t.next(), Some(Token::Asterisk));
+        assert_eq!(t.next(), Some(Token::Slash));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("x /* never\nclosed *");
+
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        let invalid = t.next_spanned().unwrap();
+        assert_eq!(invalid.node, Token::Invalid('/', 1, 2));
+        assert_eq!((invalid.span.start, invalid.span.end), (2, 19));
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new("/*/");
+        assert_eq!(t.next(), Some(Token::Invalid('/', 1, 0)));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn checked_tokens() {
+        use super::{Token, Tokenizer};
+
+        let tokens = Tokenizer::new("x := 1;")
+            .checked()
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            tokens,
+            Ok(vec![
+                Token::Name("x".to_string()),
+                Token::Assign,
+                Token::Integer(1),
+                Token::Semicolon,
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_errors() {
+        use super::{LexError, Token, Tokenizer};
+
+        let tokens = Tokenizer::new("x := 1;\n  y @ 2")
+            .checked()
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            tokens,
+            Err(LexError::UnexpectedChar {
+                c: '@',
+                line: 2,
+                col: 4,
+            })
+        );
+
+        let mut t = Tokenizer::new("\"a\\qb\" /*").checked();
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::InvalidEscape {
+                c: 'q',
+                line: 1,
+                col: 2,
+            }))
+        );
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::UnterminatedComment { line: 1, col: 7 }))
+        );
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new("a \"b").checked();
+        assert_eq!(t.next(), Some(Ok(Token::Name("a".to_string()))));
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::UnterminatedString { line: 1, col: 2 }))
+        );
+    }
+
+    #[test]
+    fn trivia() {
+        use super::{to_source, Token, Tokenizer};
+
+        let source = "let x:=0x1F ;\t// one\r\n\n  /* two\n*/print \"a\\tb\" @ 1.50  ";
+        for &newlines in &[false, true] {
+            let mut t = Tokenizer::new(source).with_trivia();
+            if newlines {
+                t = t.with_newlines();
+            }
+            let mut end = 0;
+            let mut rebuilt = String::new();
+            for token in t.with_spans() {
+                assert_eq!(token.span.start, end, "{:?}", token);
+                end = token.span.end;
+                rebuilt.push_str(&source[token.span.start..token.span.end]);
+            }
+            assert_eq!(rebuilt, source);
+        }
+
+        let tokens = Tokenizer::new("a  b /*c*/\n")
+            .with_trivia()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Name("a".to_string()),
+                Token::Whitespace("  ".to_string()),
+                Token::Name("b".to_string()),
+                Token::Whitespace(" ".to_string()),
+                Token::BlockComment("c".to_string()),
+                Token::Whitespace("\n".to_string()),
+            ]
+        );
+        assert_eq!(to_source(&tokens), "a  b /*c*/\n");
+    }
+
+    #[test]
+    fn round_trip() {
+        use super::{to_source, tokens, Tokenizer};
+
+        let original = tokens("let x:=1;");
+        assert_eq!(to_source(original.as_slice()), "let x := 1 ;");
+        assert_eq!(tokens(&to_source(original.as_slice())), original);
+
+        let source = r#"if a<>-b{print "q\"\n",'\'',1.50}
+x:=100000000000000000000.0/0.0000001;"#;
+        let original = Tokenizer::new(source).with_newlines().collect::<Vec<_>>();
+        let rebuilt = to_source(&original);
+        assert_eq!(
+            Tokenizer::new(&rebuilt).with_newlines().collect::<Vec<_>>(),
+            original
+        );
+    }
+
+    #[test]
+    fn token_list() {
+        use super::{Token, TokenKind, TokenList, Tokenizer};
+
+        let list: TokenList = Tokenizer::new("1+2").collect();
+        assert_eq!(list.len(), 3);
+        assert!(!list.is_empty());
+        assert_eq!(list.iter().next(), Some(&Token::Integer(1)));
+        assert_eq!(
+            list.kinds().collect::<Vec<_>>(),
+            vec![
+                TokenKind::Literal,
+                TokenKind::Punctuation,
+                TokenKind::Literal,
+            ]
+        );
+        assert_eq!(
+            list.into_vec(),
+            vec![Token::Integer(1), Token::Plus, Token::Integer(2)]
+        );
+
+        let empty: TokenList = Tokenizer::new("").collect();
+        assert!(empty.is_empty());
+        assert_eq!(empty, TokenList::default());
+    }
+
+    #[test]
+    fn collect_tokens() {
+        use super::{tokens, try_tokens, LexError, Token};
+
+        assert_eq!(
+            tokens("a @"),
+            vec![Token::Name("a".to_string()), Token::Invalid('@', 1, 2)]
+        );
+        assert_eq!(tokens(""), vec![]);
+        assert_eq!(
+            try_tokens("1;"),
+            Ok(vec![Token::Integer(1), Token::Semicolon])
+        );
+        assert_eq!(
+            try_tokens("a @"),
+            Err(LexError::UnexpectedChar {
+                c: '@',
+                line: 1,
+                col: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn tolerant() {
+        use super::{LexError, Token, Tokenizer};
+
+        let mut t = Tokenizer::new("a @ b\n#c").tolerant();
+        let tokens = t.by_ref().collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Name("a".to_string()),
+                Token::Invalid('@', 1, 2),
+                Token::Name("b".to_string()),
+                Token::Invalid('#', 2, 0),
+                Token::Name("c".to_string()),
+            ]
+        );
+        assert_eq!(
+            t.errors(),
+            &[
+                LexError::UnexpectedChar {
+                    c: '@',
+                    line: 1,
+                    col: 2,
+                },
+                LexError::UnexpectedChar {
+                    c: '#',
+                    line: 2,
+                    col: 0,
+                },
+            ]
+        );
+
+        let mut t = Tokenizer::new("@");
+        assert_eq!(t.next(), Some(Token::Invalid('@', 1, 0)));
+        assert!(t.errors().is_empty());
+    }
+
+    #[test]
+    fn integer_overflow() {
+        use super::{LexError, Token, Tokenizer};
+
+        let mut t = Tokenizer::new("99999999999 + 1").checked();
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::IntegerOverflow {
+                text: "99999999999".to_string(),
+                line: 1,
+                col: 0,
+            }))
+        );
+        assert_eq!(t.next(), Some(Ok(Token::Plus)));
+        assert_eq!(t.next(), Some(Ok(Token::Integer(1))));
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new("2147483647 2147483648");
+        assert_eq!(t.next(), Some(Token::Integer(i32::MAX)));
+        assert_eq!(t.next(), Some(Token::Invalid('2', 1, 11)));
+        assert_eq!(t.next(), None);
+    }
+
+    /// Tokenizes `chunks` as a stream, reading what is ready after each.
+    fn stream(chunks: &[&str]) -> Vec<super::Token> {
+        use super::Tokenizer;
+
+        let mut t = Tokenizer::streaming();
+        let mut tokens = Vec::new();
+        for chunk in chunks {
+            t.push_str(chunk);
+            tokens.extend(std::iter::from_fn(|| t.next_ready()));
+        }
+        t.finish();
+        tokens.extend(std::iter::from_fn(|| t.next_ready()));
+        tokens
+    }
+
+    #[test]
+    fn streaming() {
+        use super::tokens;
+
+        for source in &[
+            "let x := 123;",
+            "let s := \"a b\" + 1.5 ** 2; /* c */ x -= 0x1F // d\nprint s;",
+        ] {
+            let expected = tokens(source).into_vec();
+            for i in (0..=source.len()).filter(|&i| source.is_char_boundary(i)) {
+                let (first, second) = source.split_at(i);
+                assert_eq!(stream(&[first, second]), expected, "{:?}", (first, second));
+            }
+            let chars = source.chars().map(|c| c.to_string()).collect::<Vec<_>>();
+            let chunks = chars.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+            assert_eq!(stream(&chunks), expected);
+        }
+    }
+
+    #[test]
+    fn streaming_holds_back_partial_tokens() {
+        use super::{Token, Tokenizer};
+
+        let mut t = Tokenizer::streaming().with_eof();
+        t.push_str("print lon");
+        assert_eq!(t.next_ready(), Some(Token::Print));
+        assert_eq!(t.next_ready(), None);
+        t.push_str("g \"a ");
+        assert_eq!(t.next_ready(), Some(Token::Name("long".to_string())));
+        assert_eq!(t.next_ready(), None);
+        t.push_str("b\" @ 1");
+        assert_eq!(
+            t.next_ready(),
+            Some(Token::StringLiteral("a b".to_string()))
+        );
+        assert_eq!(t.next_ready(), Some(Token::Invalid('@', 1, 17)));
+        assert_eq!(t.next_ready(), None);
+        t.finish();
+        assert_eq!(t.next_ready(), Some(Token::Integer(1)));
+        assert_eq!(t.next_ready(), Some(Token::Eof));
+        assert_eq!(t.next_ready(), None);
+    }
+
+    #[test]
+    fn errors_become_invalid_tokens() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("a @ b");
+
+        assert_eq!(t.next(), Some(Token::Name("a".to_string())));
+        assert_eq!(t.next(), Some(Token::Invalid('@', 1, 2)));
+        assert_eq!(t.next(), Some(Token::Name("b".to_string())));
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new("\n\nprint  @");
+        assert_eq!(t.nth(1), Some(Token::Invalid('@', 3, 7)));
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        use super::{Token, Tokenizer};
+        let tokens = Tokenizer::new("a\r\nb\r\n\r\nc")
+            .with_spans()
+            .map(|spanned| (spanned.node, spanned.span.line, spanned.span.col))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Name("a".to_string()), 1, 0),
+                (Token::Name("b".to_string()), 2, 0),
+                (Token::Name("c".to_string()), 4, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn line_and_col() {
+        use super::Tokenizer;
+        let mut t = Tokenizer::new("a\nbc\n  d");
+        let mut starts = Vec::new();
+
+        loop {
+            t.consume_whitespace();
+            let start = (t.line, t.col);
+            if t.next().is_none() {
+                break;
+            }
+            starts.push(start);
+        }
+        assert_eq!(starts, vec![(1, 0), (2, 0), (3, 2)]);
+        assert_eq!((t.line, t.col), (3, 3));
+    }
+
+    #[test]
+    fn spans() {
+        use super::{Spanned, Token, Tokenizer};
+        let mut t = Tokenizer::new("ab := 12");
+
+        assert!(
+            t.next_spanned()
+                == Some(Spanned {
+                    node: Token::Name("ab".to_string()),
+                    span: span(0, 2, 1, 0),
+                })
+        );
+        assert!(
+            t.next_spanned()
+                == Some(Spanned {
+                    node: Token::Assign,
+                    span: span(3, 5, 1, 3),
+                })
+        );
+        assert!(
+            t.next_spanned()
+                == Some(Spanned {
+                    node: Token::Integer(12),
+                    span: span(6, 8, 1, 6),
+                })
+        );
+        assert!(t.next_spanned().is_none());
+    }
+
+    #[test]
+    fn multibyte_spans() {
+        use super::Tokenizer;
+        let spans = Tokenizer::new("£ x\n¬£ y")
+            .with_spans()
+            .map(|spanned| spanned.span)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            spans,
+            vec![
+                span(0, 2, 1, 0),
+                span(3, 4, 1, 2),
+                span(5, 7, 2, 0),
+                span(7, 9, 2, 1),
+                span(10, 11, 2, 3),
+            ]
+        );
+    }
+
+    #[cfg(feature = "unicode-xid")]
+    #[test]
+    fn multibyte_name_spans() {
+        use super::{Token, Tokenizer};
+
+        // A name's span counts the bytes of each char in it, not the chars.
+        let tokens = Tokenizer::new("café := é1\n  ñ")
+            .with_spans()
+            .map(|spanned| (spanned.node, spanned.span))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Name("café".to_string()), span(0, 5, 1, 0)),
+                (Token::Assign, span(6, 8, 1, 5)),
+                (Token::Name("é1".to_string()), span(9, 12, 1, 8)),
+                (Token::Name("ñ".to_string()), span(15, 17, 2, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn pos_after_each_token() {
+        use super::Tokenizer;
+
+        let mut t = Tokenizer::new("let x := 12;");
+        let mut positions = Vec::new();
+        while t.next().is_some() {
+            positions.push(t.pos);
+        }
+        assert_eq!(positions, vec![3, 5, 8, 11, 12]);
+
+        let mut t = Tokenizer::new("\"ñ\" é := '€'");
+        let mut positions = Vec::new();
+        while t.next().is_some() {
+            positions.push(t.pos);
+        }
+        assert_eq!(positions, vec![4, 7, 10, 16]);
+    }
+
+    #[test]
+    fn spanned_triples() {
+        use super::{Token, Tokenizer};
+
+        let triples = Tokenizer::new("1+2").spanned().collect::<Vec<_>>();
+        assert_eq!(
+            triples,
+            vec![
+                (0, Token::Integer(1), 1),
+                (1, Token::Plus, 2),
+                (2, Token::Integer(2), 3),
+            ]
+        );
+
+        assert_eq!(Tokenizer::new("").spanned().count(), 0);
+        assert_eq!(Tokenizer::new(" \n ").spanned().count(), 0);
+    }
+
+    #[test]
+    fn eof_token() {
+        use super::{Token, Tokenizer};
+
+        let mut t = Tokenizer::new("x").with_eof();
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        assert_eq!(t.next(), Some(Token::Eof));
+        assert_eq!(t.next(), None);
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new(" ").with_eof().with_spans();
+        assert_eq!(
+            t.next().map(|s| (s.node, s.span.start)),
+            Some((Token::Eof, 1))
+        );
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new("x");
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn debug_format() {
+        use super::Token;
+
+        assert_eq!(format!("{:?}", Token::Name("foo".into())), "Name(\"foo\")");
+        assert_eq!(format!("{:?}", Token::Integer(42)), "Integer(42)");
+        assert_eq!(format!("{:?}", Token::Assign), "Assign");
+    }
+
+    #[test]
+    fn display() {
+        use super::{FloatLiteral, Token};
+
+        assert_eq!(Token::Boolean(true).to_string(), "true");
+        assert_eq!(Token::Integer(42).to_string(), "42");
+        assert_eq!(Token::Float(FloatLiteral(10.0)).to_string(), "10.0");
+        assert_eq!(
+            Token::Float(FloatLiteral(1e20)).to_string(),
+            "100000000000000000000.0"
+        );
+        assert_eq!(Token::Name("foo".into()).to_string(), "foo");
+        assert_eq!(Token::Char('\'').to_string(), r"'\''");
+        assert_eq!(Token::Char('"').to_string(), "'\"'");
+        assert_eq!(
+            Token::StringLiteral("say \"hi\"\n".into()).to_string(),
+            r#""say \"hi\"\n""#
+        );
+        assert_eq!(Token::While.to_string(), "while");
+        assert_eq!(Token::Print.to_string(), "print");
+        assert_eq!(Token::Plus.to_string(), "+");
+        assert_eq!(Token::BraceLeft.to_string(), "{");
+        assert_eq!(Token::Assign.to_string(), ":=");
+        assert_eq!(Token::Ne.to_string(), "<>");
+        assert_eq!(Token::Invalid('@', 1, 0).to_string(), "<invalid>");
+    }
+
+    #[test]
+    fn symbol_tokens() {
+        assert_golden(
+            "+-*/%::=<<=",
+            "
+            Plus
+            Minus
+            Asterisk
+            Slash
+            Percent
+            Colon
+            Assign
+            Lt
+            Le
+            ",
+        );
+    }
+
+    #[test]
+    fn comma() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("a, b");
+
+        assert_eq!(t.next(), Some(Token::Name("a".to_string())));
+        assert_eq!(t.next(), Some(Token::Comma));
+        assert_eq!(t.next(), Some(Token::Name("b".to_string())));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn hash_tokens() {
+        use super::{tokens, FloatLiteral, Token};
+        use std::collections::HashMap;
+
+        let mut counts = HashMap::new();
+        for token in tokens("x := x + 1.5 + 1.5 + 1;") {
+            *counts.entry(token).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts[&Token::Name("x".to_string())], 2);
+        assert_eq!(counts[&Token::Plus], 3);
+        assert_eq!(counts[&Token::Float(FloatLiteral(1.5))], 2);
+        assert_eq!(counts[&Token::Integer(1)], 1);
+        assert_eq!(counts.len(), 6);
+    }
+
+    #[test]
+    fn comparison_tokens() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new(">= <= <> != == > < ! !!=");
+
+        assert_eq!(t.next(), Some(Token::Ge));
+        assert_eq!(t.next(), Some(Token::Le));
+        assert_eq!(t.next(), Some(Token::Ne));
+        assert_eq!(t.next(), Some(Token::Ne));
+        assert_eq!(t.next(), Some(Token::Eq));
+        assert_eq!(t.next(), Some(Token::Gt));
+        assert_eq!(t.next(), Some(Token::Lt));
+        assert_eq!(t.next(), Some(Token::Bang));
+        assert_eq!(t.next(), Some(Token::Bang));
+        assert_eq!(t.next(), Some(Token::Ne));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn compound_assignment_tokens() {
+        assert_golden(
+            "+= -= *= /= + = **=",
+            "
+            PlusAssign
+            MinusAssign
+            AsteriskAssign
+            SlashAssign
+            Plus
+            EqualSign
+            DoubleAsterisk
+            EqualSign
+            ",
+        );
+    }
+
+    #[test]
+    fn arrow() {
+        use super::{tokens, Token};
+
+        assert_eq!(
+            tokens("a->b - -> a - > b->"),
+            vec![
+                Token::Name("a".to_string()),
+                Token::Arrow,
+                Token::Name("b".to_string()),
+                Token::Minus,
+                Token::Arrow,
+                Token::Name("a".to_string()),
+                Token::Minus,
+                Token::Gt,
+                Token::Name("b".to_string()),
+                Token::Arrow,
+            ]
+        );
+        assert_eq!(tokens("-"), vec![Token::Minus]);
+        assert_eq!(Token::Arrow.to_string(), "->");
+    }
+
+    #[test]
+    fn double_asterisk() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("2 ** 3 ***");
+
+        assert_eq!(t.next(), Some(Token::Integer(2)));
+        assert_eq!(t.next(), Some(Token::DoubleAsterisk));
+        assert_eq!(t.next(), Some(Token::Integer(3)));
+        assert_eq!(t.next(), Some(Token::DoubleAsterisk));
+        assert_eq!(t.next(), Some(Token::Asterisk));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn int_tokens() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("1 2 3 123 987");
+
+        assert!(t.next() == Some(Token::Integer(1)));
+        assert!(t.next() == Some(Token::Integer(2)));
+        assert!(t.next() == Some(Token::Integer(3)));
+        assert!(t.next() == Some(Token::Integer(123)));
+        assert!(t.next() == Some(Token::Integer(987)));
+        assert!(t.next().is_none());
+    }
+
+    #[test]
+    fn string_tokens() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new(r#""hello" "a\nb" "\t\\\"""#);
+
+        assert_eq!(t.next(), Some(Token::StringLiteral("hello".to_string())));
+        assert_eq!(t.next(), Some(Token::StringLiteral("a\nb".to_string())));
+        assert_eq!(t.next(), Some(Token::StringLiteral("\t\\\"".to_string())));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn ascii_fast_path() {
+        use super::Tokenizer;
+
+        let source = "let x_1 := 0x1F + 1_000;\n\tprint \"a\\tb\", 'c', x_1 ** 2.5;\r\n\
+                      /* block */ while x_1 <> 3 { x_1 -= 1; } // done\n\
+                      for item in [true, false] { print not item @ # }";
+        assert!(source.is_ascii());
+
+        let fast = Tokenizer::new(source).with_newlines();
+        assert!(fast.ascii);
+        let mut slow = Tokenizer::new(source).with_newlines();
+        slow.ascii = false;
+        assert_eq!(
+            fast.with_spans().collect::<Vec<_>>(),
+            slow.with_spans().collect::<Vec<_>>()
+        );
+
+        let fast = Tokenizer::new(source);
+        let mut slow = Tokenizer::new(source);
+        slow.ascii = false;
+        assert_eq!(
+            fast.checked().collect::<Vec<_>>(),
+            slow.checked().collect::<Vec<_>>()
+        );
+
+        assert!(!Tokenizer::new("x := 'é';").ascii);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        use super::{tokens, Spanned, Token, Tokenizer};
+        use serde_json;
+
+        let tokens = tokens("let x := 1.5; print 'a', \"b\", true @");
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert!(json.starts_with(r#"["Let",{"Name":"x"},"Assign",{"Float":1.5},"#));
+        assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+
+        let spanned = Tokenizer::new("a\n b").with_spans().collect::<Vec<_>>();
+        let json = serde_json::to_string(&spanned).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<Spanned<Token>>>(&json).unwrap(),
+            spanned
+        );
+    }
+
+    #[test]
+    fn clone() {
+        use super::{Token, Tokenizer};
+
+        let mut t = Tokenizer::new("let x := 1;\nprint x;");
+        t.by_ref().take(3).count();
+        let mut copy = t.clone();
+        assert_eq!(copy.line(), t.line());
+
+        assert_eq!(copy.next(), Some(Token::Integer(1)));
+        assert_eq!(copy.by_ref().count(), 4);
+        assert_eq!(copy.line(), 2);
+        assert_eq!(t.line(), 1);
+
+        assert_eq!(
+            t.collect::<Vec<_>>(),
+            vec![
+                Token::Integer(1),
+                Token::Semicolon,
+                Token::Print,
+                Token::Name("x".to_string()),
+                Token::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        use super::{to_source, Token, Tokenizer};
+
+        let t = Tokenizer::new("x // note");
+        assert_eq!(t.collect::<Vec<_>>(), vec![Token::Name("x".to_string())]);
+
+        let t = Tokenizer::new("x // note").with_comments();
+        assert_eq!(
+            t.collect::<Vec<_>>(),
+            vec![
+                Token::Name("x".to_string()),
+                Token::LineComment(" note".to_string()),
+            ]
+        );
+
+        let source = "/** doc\n */ func f() { } // a\n// b\ny";
+        let tokens = Tokenizer::new(source).with_comments().collect::<Vec<_>>();
+        assert_eq!(tokens[0], Token::BlockComment("* doc\n ".to_string()));
+        assert_eq!(tokens[7], Token::LineComment(" a".to_string()));
+        assert_eq!(tokens[8], Token::LineComment(" b".to_string()));
+        assert_eq!(
+            Tokenizer::new(&to_source(&tokens))
+                .with_comments()
+                .collect::<Vec<_>>(),
+            tokens
+        );
+    }
+
+    #[test]
+    fn shebang() {
+        use super::{Token, Tokenizer};
+
+        let mut t = Tokenizer::new("#!/usr/bin/env alan\nprint 1;").skip_shebang();
+        assert_eq!(t.next(), Some(Token::Print));
+        assert_eq!(t.line(), 2);
+        assert_eq!(t.next(), Some(Token::Integer(1)));
+
+        let t = Tokenizer::new("print 1; #!x").skip_shebang();
+        assert_eq!(
+            t.collect::<Vec<_>>(),
+            vec![
+                Token::Print,
+                Token::Integer(1),
+                Token::Semicolon,
+                Token::Invalid('#', 1, 9),
+                Token::Bang,
+                Token::Name("x".to_string()),
+            ]
+        );
+
+        let t = Tokenizer::new(" #!x").skip_shebang();
+        assert_eq!(t.count(), 3);
+
+        let t = Tokenizer::new("#!/bin/alan").skip_shebang().with_newlines();
+        assert_eq!(t.count(), 0);
+    }
+
+    #[test]
+    fn position() {
+        use super::{Token, Tokenizer};
+
+        let mut t = Tokenizer::new("a\nb");
+        assert_eq!((t.line(), t.col()), (1, 0));
+        assert_eq!(t.next(), Some(Token::Name("a".to_string())));
+        assert_eq!((t.line(), t.col()), (1, 1));
+        assert_eq!(t.next(), Some(Token::Name("b".to_string())));
+        assert_eq!((t.line(), t.col()), (2, 1));
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new("// one\n/* two\n */ \"three\n\" x").with_newlines();
+        assert_eq!(t.next(), Some(Token::Newline));
+        assert_eq!(t.line(), 2);
+        assert_eq!(t.next(), Some(Token::StringLiteral("three\n".to_string())));
+        assert_eq!((t.line(), t.col()), (4, 1));
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        assert_eq!((t.line(), t.col()), (4, 3));
+    }
+
+    #[test]
+    fn case_insensitive_keywords() {
+        use super::{Token, Tokenizer};
+
+        let source = "If WHILE While while True Name";
+        assert_eq!(
+            Tokenizer::new(source).collect::<Vec<_>>(),
+            vec![
+                Token::Name("If".to_string()),
+                Token::Name("WHILE".to_string()),
+                Token::Name("While".to_string()),
+                Token::While,
+                Token::Name("True".to_string()),
+                Token::Name("Name".to_string()),
+            ]
+        );
+        assert_eq!(
+            Tokenizer::new(source)
+                .case_insensitive_keywords()
+                .collect::<Vec<_>>(),
+            vec![
+                Token::If,
+                Token::While,
+                Token::While,
+                Token::While,
+                Token::Boolean(true),
+                Token::Name("Name".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        use super::try_tokens;
+        use std::error::Error;
+
+        fn lex(source: &str) -> Result<usize, Box<dyn Error>> {
+            Ok(try_tokens(source)?.len())
+        }
+
+        assert_eq!(lex("a b").unwrap(), 2);
+        let messages = [
+            ("x @", "1:2: unexpected char '@'"),
+            ("\n  \"a\\qb\"", "2:4: unknown escape '\\q'"),
+            ("\"\\u{D800}\"", "1:1: '\\u{D800}' is not a valid char"),
+            ("''", "1:0: empty char literal"),
+            ("/* x", "1:0: unterminated block comment"),
+            (
+                "99999999999",
+                "1:0: integer literal '99999999999' is too large",
+            ),
+            ("0xZ", "1:0: malformed number literal '0xZ'"),
+        ];
+        for &(source, message) in &messages {
+            let err = lex(source).unwrap_err();
+            assert_eq!(err.to_string(), message);
+            assert!(err.source().is_none());
+        }
+    }
+
+    #[test]
+    fn kinds() {
+        use super::{tokens, Token, TokenKind};
+
+        assert_eq!(Token::While.kind(), TokenKind::Keyword);
+        assert_eq!(Token::Plus.kind(), TokenKind::Punctuation);
+
+        let kinds = tokens("let x := \"s\" <> 'c' or true; @")
+            .kinds()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Keyword,
+                TokenKind::Identifier,
+                TokenKind::Operator,
+                TokenKind::Literal,
+                TokenKind::Operator,
+                TokenKind::Literal,
+                TokenKind::Keyword,
+                TokenKind::Literal,
+                TokenKind::Punctuation,
+                TokenKind::Other,
+            ]
+        );
+    }
+
+    #[test]
+    fn analyze() {
+        use super::{analyze, LexError, Spanned, Token};
+
+        assert_eq!(
+            analyze("x := 1"),
+            Ok(vec![
+                Spanned {
+                    node: Token::Name("x".to_string()),
+                    span: span(0, 1, 1, 0),
+                },
+                Spanned {
+                    node: Token::Assign,
+                    span: span(2, 4, 1, 2),
+                },
+                Spanned {
+                    node: Token::Integer(1),
+                    span: span(5, 6, 1, 5),
+                },
+            ])
+        );
+        assert_eq!(
+            analyze("x := 1;\ny @ \"unterminated"),
+            Err(LexError::UnexpectedChar {
+                c: '@',
+                line: 2,
+                col: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn dump() {
+        use super::dump;
+
+        assert_eq!(dump("let x"), r#"[1:0 Let][1:4 Name("x")]"#);
+        assert_eq!(
+            dump("1\n @ 'a'"),
+            "[1:0 Integer(1)][2:1 Invalid('@', 2, 1)][2:3 Char('a')]"
+        );
+        assert_eq!(dump(""), "");
+    }
+
+    #[test]
+    fn size_hint() {
+        use super::Tokenizer;
+
+        let source = "let x := [1, 22, 333];  // done\nprint x;";
+        let mut t = Tokenizer::new(source);
+        assert_eq!(t.size_hint(), (0, Some(source.len())));
+        let count = Tokenizer::new(source).count();
+        assert!(t.size_hint().1.unwrap() >= count);
+
+        t.by_ref().take(4).count();
+        let (_, upper) = t.size_hint();
+        assert!(upper.unwrap() >= count - 4);
+        assert!(upper.unwrap() < source.len());
+
+        let mut t = Tokenizer::new("  ").with_eof();
+        assert_eq!(t.size_hint(), (1, Some(3)));
+        assert_eq!(t.by_ref().count(), 1);
+        assert_eq!(t.size_hint(), (0, Some(0)));
+
+        let t = Tokenizer::new("a b").with_spans();
+        assert_eq!(t.size_hint(), (0, Some(3)));
+    }
+
+    #[test]
+    fn raw_strings() {
+        use super::{LexError, Token, Tokenizer};
+
+        let mut t = Tokenizer::new("x \"\"\"first \\n\n  \"second\" \"\"\" y").checked();
+        assert_eq!(t.next(), Some(Ok(Token::Name("x".to_string()))));
+        assert_eq!(
+            t.next(),
+            Some(Ok(Token::StringLiteral(
+                "first \\n\n  \"second\" ".to_string()
+            )))
+        );
+        assert_eq!(t.next(), Some(Ok(Token::Name("y".to_string()))));
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new("\"\"\"\"\"\" \"\" \"\"\"a\nb\"\"\" c");
+        assert_eq!(t.next(), Some(Token::StringLiteral(String::new())));
+        assert_eq!(t.next(), Some(Token::StringLiteral(String::new())));
+        assert_eq!(t.next(), Some(Token::StringLiteral("a\nb".to_string())));
+        assert_eq!(t.line(), 2);
+        assert_eq!(t.next(), Some(Token::Name("c".to_string())));
+
+        let mut t = Tokenizer::new("\n \"\"\"open\n\"\"").checked();
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::UnterminatedString { line: 2, col: 1 }))
+        );
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        use super::{LexError, Token, Tokenizer};
+
+        let mut t = Tokenizer::new(r#""\u{41}" "\x41\x7e" "\u{1F600}!" '\u{e9}'"#).checked();
+        assert_eq!(t.next(), Some(Ok(Token::StringLiteral("A".to_string()))));
+        assert_eq!(t.next(), Some(Ok(Token::StringLiteral("A~".to_string()))));
+        assert_eq!(
+            t.next(),
+            Some(Ok(Token::StringLiteral("\u{1F600}!".to_string())))
+        );
+        assert_eq!(t.next(), Some(Ok(Token::Char('é'))));
+        assert_eq!(t.next(), None);
+
+        let malformed = [
+            r#""\u{}""#,
+            r#""a\uG""#,
+            r#""\u{41""#,
+            r#""\u{1234567}""#,
+            r#""\x4""#,
+        ];
+        for source in &malformed {
+            let mut t = Tokenizer::new(source).checked();
+            let err = t.next().unwrap().unwrap_err();
+            match err {
+                LexError::MalformedEscape { line: 1, .. } => {}
+                err => panic!("{}: {:?}", source, err),
+            }
+            assert_eq!(t.next(), None, "{}", source);
+        }
+
+        let mut t = Tokenizer::new(r#"x "\u{D800}" "ab\u{110000}""#).checked();
+        assert_eq!(t.next(), Some(Ok(Token::Name("x".to_string()))));
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::InvalidCodePoint {
+                value: 0xD800,
+                line: 1,
+                col: 3,
+            }))
+        );
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::InvalidCodePoint {
+                value: 0x110000,
+                line: 1,
+                col: 16,
+            }))
+        );
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn char_tokens() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new(r"'a' '\n' '\'' '\\' 'é'");
+
+        assert_eq!(t.next(), Some(Token::Char('a')));
+        assert_eq!(t.next(), Some(Token::Char('\n')));
+        assert_eq!(t.next(), Some(Token::Char('\'')));
+        assert_eq!(t.next(), Some(Token::Char('\\')));
+        assert_eq!(t.next(), Some(Token::Char('é')));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn invalid_char_tokens() {
+        use super::{LexError, Token, Tokenizer};
+
+        let mut t = Tokenizer::new("'' x 'ab' y\n  'a\nz").checked();
+        assert_eq!(t.next(), Some(Err(LexError::EmptyChar { line: 1, col: 0 })));
+        assert_eq!(t.next(), Some(Ok(Token::Name("x".to_string()))));
+        assert_eq!(t.next(), Some(Err(LexError::MultiChar { line: 1, col: 5 })));
+        assert_eq!(t.next(), Some(Ok(Token::Name("y".to_string()))));
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::UnterminatedChar { line: 2, col: 2 }))
+        );
+        assert_eq!(t.next(), Some(Ok(Token::Name("z".to_string()))));
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new(r"'\q'").checked();
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::InvalidEscape {
+                c: 'q',
+                line: 1,
+                col: 1,
+            }))
+        );
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn invalid_string_tokens() {
+        use super::{Token, Tokenizer};
+
+        let mut t = Tokenizer::new(r#""bad \q" x"#);
+        assert_eq!(t.next(), Some(Token::Invalid('"', 1, 0)));
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        assert_eq!(t.next(), None);
+
+        let mut t = Tokenizer::new(r#""oops"#);
+        assert_eq!(t.next(), Some(Token::Invalid('"', 1, 0)));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn radix_int_tokens() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("0xFF 0Xff 0x7fffffff 0b1010 0B1 0");
+
+        assert_eq!(t.next(), Some(Token::Integer(255)));
+        assert_eq!(t.next(), Some(Token::Integer(255)));
+        assert_eq!(t.next(), Some(Token::Integer(i32::MAX)));
+        assert_eq!(t.next(), Some(Token::Integer(10)));
+        assert_eq!(t.next(), Some(Token::Integer(1)));
+        assert_eq!(t.next(), Some(Token::Integer(0)));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn malformed_radix_ints() {
+        use super::{LexError, Token, Tokenizer};
+
+        let mut t = Tokenizer::new("0x; 0xG 0b102 0x80000000").checked();
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::MalformedNumber {
+                text: "0x".to_string(),
+                line: 1,
+                col: 0,
+            }))
+        );
+        assert_eq!(t.next(), Some(Ok(Token::Semicolon)));
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::MalformedNumber {
+                text: "0xG".to_string(),
+                line: 1,
+                col: 4,
+            }))
+        );
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::MalformedNumber {
+                text: "0b102".to_string(),
+                line: 1,
+                col: 8,
+            }))
+        );
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::IntegerOverflow {
+                text: "0x80000000".to_string(),
+                line: 1,
+                col: 14,
+            }))
+        );
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn digit_separators() {
+        use super::{FloatLiteral, Token, Tokenizer};
+        let mut t = Tokenizer::new("1_000 1_000_000 0xFF_FF 0b1_0 1_0.2_5");
+
+        assert_eq!(t.next(), Some(Token::Integer(1000)));
+        assert_eq!(t.next(), Some(Token::Integer(1_000_000)));
+        assert_eq!(t.next(), Some(Token::Integer(0xFFFF)));
+        assert_eq!(t.next(), Some(Token::Integer(2)));
+        assert_eq!(t.next(), Some(Token::Float(FloatLiteral(10.25))));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn misplaced_digit_separators() {
+        use super::{LexError, Token, Tokenizer};
+
+        for &text in &["100_", "1__0", "1_.5", "0x_FF", "0b1_"] {
+            let error = Tokenizer::new(text).checked().next();
+            assert_eq!(
+                error,
+                Some(Err(LexError::MalformedNumber {
+                    text: text.to_string(),
+                    line: 1,
+                    col: 0,
+                })),
+                "{}",
+                text
+            );
+        }
+
+        // A leading `_` starts a name instead.
+        let mut t = Tokenizer::new("_100").checked();
+        assert_eq!(t.next(), Some(Ok(Token::Name("_100".to_string()))));
+    }
+
+    #[test]
+    fn peek_nth() {
+        use super::{PeekableTokenizer, Token, Tokenizer};
+        let mut t = PeekableTokenizer::new(Tokenizer::new("a := b"));
+
+        assert_eq!(t.peek_nth(1), Some(&Token::Assign));
+        assert_eq!(t.peek_nth(0), Some(&Token::Name("a".to_string())));
+        assert_eq!(t.peek_nth(2), Some(&Token::Name("b".to_string())));
+        assert_eq!(t.peek_nth(3), None);
+
+        assert_eq!(t.next(), Some(Token::Name("a".to_string())));
+        assert_eq!(t.peek(), Some(&Token::Assign));
+        assert_eq!(t.next(), Some(Token::Assign));
+        assert_eq!(t.next(), Some(Token::Name("b".to_string())));
+        assert_eq!(t.peek_nth(1), None);
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn float_tokens() {
+        use super::{FloatLiteral, LexError, Token, Tokenizer};
+        let mut t = Tokenizer::new("3.25 10.0 0.5");
+
+        assert_eq!(t.next(), Some(Token::Float(FloatLiteral(3.25))));
+        assert_eq!(t.next(), Some(Token::Float(FloatLiteral(10.0))));
+        assert_eq!(t.next(), Some(Token::Float(FloatLiteral(0.5))));
+        assert_eq!(t.next(), None);
+
+        // A float too large for an `f64` is an error, as an integer too
+        // large for an `i32` is, rather than infinity.
+        let text = format!("1{}.5", "0".repeat(400));
+        let source = format!("{} 1", text);
+        let mut t = Tokenizer::new(&source).checked();
+        assert_eq!(
+            t.next(),
+            Some(Err(LexError::FloatOverflow {
+                text,
+                line: 1,
+                col: 0,
+            }))
+        );
+        assert_eq!(t.next(), Some(Ok(Token::Integer(1))));
+    }
+
+    #[test]
+    fn dot_after_number_or_name() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("x.y 1.z 2.");
+
+        assert_eq!(t.next(), Some(Token::Name("x".to_string())));
+        assert_eq!(t.next(), Some(Token::Dot));
+        assert_eq!(t.next(), Some(Token::Name("y".to_string())));
+        assert_eq!(t.next(), Some(Token::Integer(1)));
+        assert_eq!(t.next(), Some(Token::Dot));
+        assert_eq!(t.next(), Some(Token::Name("z".to_string())));
+        assert_eq!(t.next(), Some(Token::Integer(2)));
+        assert_eq!(t.next(), Some(Token::Dot));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn keyword_tokens() {
+        assert_golden(
+            "and array assert break continue else func if let not or print return while",
+            "
+            And
+            Array
+            Assert
+            Break
+            Continue
+            Else
+            Func
+            If
+            Let
+            Not
+            Or
+            Print
+            Return
+            While
+            ",
+        );
+    }
+
+    #[test]
+    fn for_in_keywords() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("for i in xs fork inside");
+
+        assert_eq!(t.next(), Some(Token::For));
+        assert_eq!(t.next(), Some(Token::Name("i".to_string())));
+        assert_eq!(t.next(), Some(Token::In));
+        assert_eq!(t.next(), Some(Token::Name("xs".to_string())));
+        assert_eq!(t.next(), Some(Token::Name("fork".to_string())));
+        assert_eq!(t.next(), Some(Token::Name("inside".to_string())));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn boolean_tokens() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("true false truthy trueValue False");
+
+        assert_eq!(t.next(), Some(Token::Boolean(true)));
+        assert_eq!(t.next(), Some(Token::Boolean(false)));
+        assert_eq!(t.next(), Some(Token::Name("truthy".to_string())));
+        assert_eq!(t.next(), Some(Token::Name("trueValue".to_string())));
+        assert_eq!(t.next(), Some(Token::Name("False".to_string())));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn operator_predicates() {
+        use super::Token;
+
+        assert!(Token::Plus.is_binary_op());
+        assert!(Token::Or.is_binary_op());
+        assert!(!Token::Bang.is_binary_op());
+        assert!(!Token::PlusAssign.is_binary_op());
+
+        assert!(Token::Le.is_comparison());
+        assert!(Token::Ne.is_comparison());
+        assert!(!Token::Plus.is_comparison());
+
+        assert!(Token::While.is_keyword());
+        assert!(!Token::Boolean(true).is_keyword());
+        assert!(!Token::Name("while".to_string()).is_keyword());
+
+        let (_, plus) = Token::Plus.binding_power().unwrap();
+        let (asterisk, _) = Token::Asterisk.binding_power().unwrap();
+        assert!(asterisk > plus);
+        let (left, right) = Token::DoubleAsterisk.binding_power().unwrap();
+        assert!(left > right);
+        assert_eq!(Token::Semicolon.binding_power(), None);
+    }
+
+    #[cfg(feature = "unicode-xid")]
+    #[test]
+    fn unicode_names() {
+        use super::{tokens, Token};
+
+        assert_eq!(
+            tokens("let café := λ_1 + Δx; print 名前"),
+            vec![
+                Token::Let,
+                Token::Name("café".to_string()),
+                Token::Assign,
+                Token::Name("λ_1".to_string()),
+                Token::Plus,
+                Token::Name("Δx".to_string()),
+                Token::Semicolon,
+                Token::Print,
+                Token::Name("名前".to_string()),
+            ]
+        );
+        // Only chars that can begin a name do so.
+        assert_eq!(
+            tokens("·x"),
+            vec![Token::Invalid('·', 1, 0), Token::Name("x".to_string())]
+        );
+        // Combining marks and connectors such as U+00B7 are neither letters
+        // nor digits, but can continue a name all the same.
+        assert_eq!(
+            tokens("cafe\u{301} x·y"),
+            vec![
+                Token::Name("cafe\u{301}".to_string()),
+                Token::Name("x·y".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(not(feature = "unicode-xid"))]
+    #[test]
+    fn ascii_names() {
+        use super::{tokens, Token};
+
+        assert_eq!(
+            tokens("cafe\u{301} é"),
+            vec![
+                Token::Name("cafe".to_string()),
+                Token::Invalid('\u{301}', 1, 4),
+                Token::Invalid('é', 1, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn keyword_lookup() {
+        use super::{keyword, Token};
+
+        assert_eq!(keyword("while"), Some(Token::While));
+        assert_eq!(keyword("xyz"), None);
+        assert_eq!(keyword("While"), None);
+    }
+
+    #[test]
+    fn name_tokens() {
+        use super::{Token, Tokenizer};
+        let mut t = Tokenizer::new("and xxx if If");
 
         assert!(t.next() == Some(Token::And));
         assert!(t.next() == Some(Token::Name("xxx".to_string())));
         assert!(t.next() == Some(Token::If));
         assert!(t.next() == Some(Token::Name("If".to_string())));
-        assert!(t.next() == None);
+        assert!(t.next().is_none());
+    }
+
+    #[test]
+    fn underscore_names() {
+        use super::{tokens, Token};
+
+        assert_eq!(
+            tokens("_foo __ _ _1 a_"),
+            vec![
+                Token::Name("_foo".to_string()),
+                Token::Name("__".to_string()),
+                Token::Name("_".to_string()),
+                Token::Name("_1".to_string()),
+                Token::Name("a_".to_string()),
+            ]
+        );
+        assert_eq!(tokens("_while"), vec![Token::Name("_while".to_string())]);
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(source in "\\PC*|[0-9a-z_.\"'\\\\/*{}()!<>=:+\\- \n\té]{0,64}") {
+            use super::{dump, to_source, Tokenizer};
+
+            Tokenizer::new(&source).count();
+            Tokenizer::new(&source).with_newlines().with_comments().with_eof().count();
+            Tokenizer::new(&source).checked().count();
+            Tokenizer::new(&source).spanned().count();
+            Tokenizer::new(&source).tolerant().count();
+            dump(&source);
+            to_source(&Tokenizer::new(&source).collect::<Vec<_>>());
+            Tokenizer::new(&source).with_spans().count();
+        }
     }
 }